@@ -1,30 +1,68 @@
 use crate::{
+    bridge,
+    callback::{CallbackHandler, CallbackMap, Confirmation},
+    chunk,
+    collapse::Collapser,
     command::{GameCommand, GameCommandMap},
     config::Config,
+    delivery::Delivery,
+    event::{self, Event},
+    format::OutputFormat,
+    game::{Input, Output},
+    history,
+    idle::{self, IdlePolicy},
+    link::{self, PendingLink},
+    parse::Player,
+    playlist,
+    rules::{self, Action, Rule},
+    scrollback::Scrollback,
+    status,
+    store::{PlaylistEntry, Store},
+    tempban,
+    trigger::{self, RateLimit, Trigger},
+    vote::{self, MapVote},
 };
 use futures_util::future::BoxFuture;
 use itertools::Itertools;
 use std::{
     collections::{HashMap, HashSet},
     convert::TryFrom,
-    process,
+    mem, process,
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tgbot::{
     longpoll::LongPoll,
-    methods::{GetMe, SendMessage, SetMyCommands},
-    types::{BotCommand, Command, MessageKind, Update, UpdateKind},
-    webhook, Api, Config as ApiConfig, UpdateHandler,
+    methods::{AnswerCallbackQuery, EditMessageText, GetMe, SendMessage, SetMyCommands},
+    types::{
+        BotCommand, CallbackQuery, Command, InlineKeyboardButton, InlineKeyboardMarkup, Integer,
+        MessageKind, Poll, Update, UpdateKind,
+    },
+    webhook, Api, Config as ApiConfig, ExecuteError, UpdateHandler,
 };
 use tokio::{
-    sync::{mpsc, Mutex, RwLock},
+    sync::{mpsc, oneshot, Mutex, Notify, RwLock},
     task::JoinHandle,
+    time,
 };
 
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The most of a response kept in memory, in bytes. The rest is left out of the file it is sent as
+const RESPONSE_LIMIT: usize = 16 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct BotInstance {
     api: Api,
     webhook: u16,
+    file_threshold: usize,
+    collapse_window: Duration,
+    collapse_numbers: bool,
+    status_chats: Vec<i64>,
+    status_interval: Duration,
+    idle_policy: IdlePolicy,
+    idle_timeout: Duration,
+    delivery: Delivery,
     context: Context,
 }
 
@@ -33,40 +71,260 @@ impl BotInstance {
         let mut api_config = ApiConfig::new(config.token.clone());
 
         if let Some(proxy) = config.proxy.clone() {
-            api_config = api_config.proxy(proxy).or_else(|e| Err(e.to_string()))?;
+            api_config = api_config.proxy(proxy).map_err(|e| e.to_string())?;
         }
 
-        let api = Api::new(api_config).or_else(|e| Err(e.to_string()))?;
+        let api = Api::new(api_config).map_err(|e| e.to_string())?;
 
         Ok(Self {
+            delivery: Delivery::init(api.clone()),
             api,
             webhook: config.webhook,
-            context: Context::init(config.user.clone()),
+            file_threshold: config.file_threshold,
+            collapse_window: config.collapse_window,
+            collapse_numbers: config.collapse_numbers,
+            status_chats: config.status_chats.clone(),
+            status_interval: config.status_interval,
+            idle_policy: config.idle_policy,
+            idle_timeout: config.idle_timeout,
+            context: Context::init(config)?,
         })
     }
 
     pub async fn handle_output(
         self,
-        mut output_receiver: mpsc::Receiver<String>,
+        mut output_receiver: mpsc::Receiver<Output>,
+        input_sender: mpsc::Sender<Input>,
     ) -> JoinHandle<()> {
         let output_handler = tokio::spawn(async move {
-            while let Some(output) = output_receiver.recv().await {
-                if output.starts_with("Commands:\n") {
-                    *self.context.commands.write().await =
-                        Some(Arc::new(GameCommandMap::init(output)));
-                } else {
-                    let output_chat = (*self.context.output_chat.lock().await).clone();
-
-                    for chat_id in output_chat {
-                        match self
-                            .api
-                            .execute(SendMessage::new(chat_id, output.clone()))
-                            .await
-                        {
-                            Ok(_) => (),
-                            Err(e) => eprintln!("{}", e.to_string()),
+            let handler =
+                BotUpdateHandler::new(self.api.clone(), input_sender, self.context.clone());
+
+            let mut response = String::new();
+            let mut response_len = 0;
+
+            let mut is_help = false;
+            let mut is_large = false;
+            let mut is_truncated = false;
+
+            // Whether the game process was restarted and has not listed its commands again yet
+            let mut is_restarted = false;
+
+            let mut collapser = Collapser::new(self.collapse_window, self.collapse_numbers);
+
+            loop {
+                let deadline = collapser.deadline();
+                let flush_at = time::Instant::from_std(deadline.unwrap_or_else(Instant::now));
+
+                let output = tokio::select! {
+                    output = output_receiver.recv() => match output {
+                        Some(output) => output,
+                        None => break,
+                    },
+                    // The copies of a repeated line are sent once they were held for the window
+                    _ = time::sleep_until(flush_at), if deadline.is_some() => {
+                        if let Some(line) = collapser.flush(Instant::now()) {
+                            for (chat_id, format) in self.context.output_targets().await {
+                                self.delivery.stream(chat_id, line.clone(), format).await;
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                match output {
+                    Output::Line {
+                        text,
+                        level,
+                        is_response,
+                    } => {
+                        self.context.scrollback.push(&text);
+
+                        if !is_response {
+                            let event = event::parse(level, &text);
+                            let is_chat = matches!(event, Some(Event::Chat { .. }));
+
+                            self.handle_event(&handler, event).await;
+
+                            // Players could otherwise run the commands by saying the matched text
+                            if !is_chat {
+                                self.run_triggers(&handler, &text).await;
+                            }
+
+                            // The output rules apply to the log of the game server, not to the
+                            // responses to commands
+                            let action = rules::find(&self.context.rules.read().await, &text)
+                                .map(|rule| rule.action.clone());
+
+                            match action {
+                                Some(Action::Drop) => continue,
+                                Some(Action::Forward(chats)) => {
+                                    for chat_id in chats {
+                                        let format = self.context.format_of(chat_id).await;
+                                        self.delivery.stream(chat_id, text.clone(), format).await;
+                                    }
+                                    continue;
+                                }
+                                Some(Action::Highlight(emoji)) => {
+                                    // A new message notifies, unlike the edits of a streamed one
+                                    let text = format!(
+                                        "{} {} @{}",
+                                        emoji,
+                                        text.trim_end(),
+                                        self.context.user
+                                    );
+
+                                    for (chat_id, _) in self.context.output_targets().await {
+                                        self.delivery
+                                            .send(chat_id, text.clone(), OutputFormat::Plain)
+                                            .await;
+                                    }
+                                    continue;
+                                }
+                                Some(Action::Alert) => {
+                                    let text = format!("⚠️ {}", text.trim_end());
+
+                                    for chat_id in self.context.alert_targets().await {
+                                        self.delivery
+                                            .send(chat_id, text.clone(), OutputFormat::Plain)
+                                            .await;
+                                    }
+                                }
+                                None => {}
+                            }
+                        }
+
+                        if is_response {
+                            if response.is_empty() && text == "Commands:\n" {
+                                is_help = true;
+                            }
+
+                            if response.len() + text.len() <= RESPONSE_LIMIT {
+                                response.push_str(&text);
+                            } else {
+                                is_truncated = true;
+                            }
+                            response_len += chunk::utf16_len(&text);
+
+                            if self.file_threshold > 0 && response_len > self.file_threshold {
+                                is_large = true;
+                            }
+
+                            // With a file threshold, the response is held until it is complete,
+                            // then sent either in messages or as a file, never both
+                            if is_help || self.file_threshold > 0 {
+                                continue;
+                            }
+                        }
+
+                        // Responses are sent as they are, after the copies held before them
+                        let lines = if is_response {
+                            collapser.end().into_iter().chain([text]).collect()
+                        } else {
+                            collapser.push(&text, Instant::now())
+                        };
+
+                        for (chat_id, format) in self.context.output_targets().await {
+                            for line in &lines {
+                                self.delivery.stream(chat_id, line.clone(), format).await;
+                            }
+                        }
+                    }
+                    Output::End => {
+                        let mut response = mem::take(&mut response);
+                        response_len = 0;
+
+                        if mem::take(&mut is_truncated) {
+                            response.push_str(&format!(
+                                "\n(The response was cut off at {} MB)\n",
+                                RESPONSE_LIMIT / 1024 / 1024
+                            ));
+                        }
+
+                        if is_help {
+                            let is_startup = self
+                                .context
+                                .commands
+                                .write()
+                                .await
+                                .replace(Arc::new(GameCommandMap::init(response)))
+                                .is_none();
+
+                            // The server is ready once it has listed its commands
+                            if is_startup || mem::take(&mut is_restarted) {
+                                tokio::spawn(host_next(handler.clone(), false));
+                            }
+                        } else {
+                            let held = collapser.end();
+                            let is_held = self.file_threshold > 0 && !is_large;
+
+                            for (chat_id, format) in self.context.output_targets().await {
+                                if let Some(line) = &held {
+                                    self.delivery.stream(chat_id, line.clone(), format).await;
+                                }
+
+                                if is_held && !response.is_empty() {
+                                    self.delivery
+                                        .stream(chat_id, response.clone(), format)
+                                        .await;
+                                }
+
+                                self.delivery.end_stream(chat_id).await;
+
+                                if is_large {
+                                    self.delivery
+                                        .send_document(
+                                            chat_id,
+                                            String::from("output.txt"),
+                                            response.clone(),
+                                            format,
+                                        )
+                                        .await;
+                                }
+                            }
+                        }
+
+                        is_help = false;
+                        is_large = false;
+                    }
+                    Output::Alert { text, attachment } => {
+                        for chat_id in self.context.alert_targets().await {
+                            self.delivery
+                                .send(chat_id, text.clone(), OutputFormat::Plain)
+                                .await;
+
+                            if let Some(attachment) = &attachment {
+                                self.delivery
+                                    .send_document(
+                                        chat_id,
+                                        attachment.name.clone(),
+                                        attachment.text.clone(),
+                                        OutputFormat::Plain,
+                                    )
+                                    .await;
+                            }
                         }
                     }
+                    Output::Restart => {
+                        // The response in progress will never end
+                        response.clear();
+                        response_len = 0;
+                        is_truncated = false;
+                        is_help = false;
+                        is_large = false;
+
+                        let held = collapser.end();
+
+                        for (chat_id, format) in self.context.output_targets().await {
+                            if let Some(line) = &held {
+                                self.delivery.stream(chat_id, line.clone(), format).await;
+                            }
+                            self.delivery.end_stream(chat_id).await;
+                        }
+
+                        self.handle_stop().await;
+                        is_restarted = true;
+                    }
                 }
             }
         });
@@ -74,10 +332,190 @@ impl BotInstance {
         output_handler
     }
 
-    pub async fn handle_input(self, input_sender: mpsc::Sender<String>) -> JoinHandle<()> {
+    pub async fn handle_idle(self, input_sender: mpsc::Sender<Input>) -> JoinHandle<()> {
+        let idle_handler = tokio::spawn(async move {
+            if self.idle_policy != IdlePolicy::None {
+                idle::run(
+                    BotUpdateHandler::new(self.api, input_sender, self.context),
+                    self.delivery,
+                    self.idle_policy,
+                    self.idle_timeout,
+                )
+                .await;
+            }
+        });
+        idle_handler
+    }
+
+    pub async fn handle_tempbans(self, input_sender: mpsc::Sender<Input>) -> JoinHandle<()> {
+        let tempban_handler = tokio::spawn(async move {
+            tempban::run(BotUpdateHandler::new(self.api, input_sender, self.context)).await;
+        });
+        tempban_handler
+    }
+
+    // Events are handled in their own tasks when they need to run commands, as the response
+    // would only arrive after this returns
+    async fn handle_event(&self, handler: &BotUpdateHandler, event: Option<Event>) {
+        if event.as_ref().is_some_and(Event::is_replaced_by_playlist) {
+            match playlist::is_in_use(&self.context.store) {
+                // The playlist hosts its next map instead
+                Ok(true) => return,
+                Ok(false) => (),
+                Err(e) => log!("Failed to read the playlist: {}", e),
+            }
+        }
+
+        match event {
+            Some(Event::Chat { player, message }) => {
+                if let Some(chat_id) = self.context.bridge_chat {
+                    self.delivery
+                        .send(
+                            chat_id,
+                            format!("{}: {}", player, message),
+                            OutputFormat::Plain,
+                        )
+                        .await;
+                }
+
+                tokio::spawn(link::handle_chat(
+                    handler.clone(),
+                    self.delivery.clone(),
+                    player,
+                    message,
+                ));
+            }
+            Some(Event::Join { player, uuid }) => {
+                match self.context.store.start_session(&uuid, &player) {
+                    Ok(_) => (),
+                    Err(e) => log!("Failed to record the session of {}: {}", player, e),
+                }
+
+                let players = {
+                    let mut online_players = self.context.online_players.lock().await;
+                    online_players.insert(player);
+                    online_players.len()
+                };
+                self.context.player_joined.notify_one();
+
+                if let Some(id) = *self.context.current_match.lock().await {
+                    match self.context.store.update_match_peak(id, players) {
+                        Ok(_) => (),
+                        Err(e) => log!("Failed to record the players of the match: {}", e),
+                    }
+                }
+            }
+            Some(Event::Leave { player, uuid }) => {
+                match self.context.store.end_session(uuid.as_deref(), &player) {
+                    Ok(_) => (),
+                    Err(e) => log!("Failed to record the session of {}: {}", player, e),
+                }
+
+                self.context.online_players.lock().await.remove(&player);
+            }
+            Some(Event::GameStart { map }) => {
+                self.end_match(None, None).await;
+
+                let mode = self.context.mode.lock().await.clone();
+                let players = self.context.online_players.lock().await.len();
+
+                match self
+                    .context
+                    .store
+                    .start_match(map.as_deref(), mode.as_deref(), players)
+                {
+                    Ok(id) => {
+                        *self.context.current_match.lock().await = Some(id);
+
+                        if map.is_none() {
+                            tokio::spawn(history::fill_map(handler.clone(), id));
+                        }
+                    }
+                    Err(e) => log!("Failed to record the match: {}", e),
+                }
+            }
+            Some(Event::GameOver { wave, winner }) => {
+                self.end_match(wave, winner.as_deref()).await;
+
+                tokio::spawn(host_next(handler.clone(), true));
+            }
+            Some(Event::Stop) => self.handle_stop().await,
+            None => (),
+        }
+    }
+
+    // Runs the commands of the triggers the line matches, unless they ran too often
+    async fn run_triggers(&self, handler: &BotUpdateHandler, line: &str) {
+        let commands = self
+            .context
+            .triggers
+            .read()
+            .await
+            .iter()
+            .filter_map(|trigger| Some((trigger.clone(), trigger.command_for(line)?)))
+            .collect::<Vec<_>>();
+
+        for (trigger, command) in commands {
+            let skipped = match self
+                .context
+                .trigger_limit
+                .lock()
+                .await
+                .allow(&trigger, Instant::now())
+            {
+                Some(skipped) => skipped,
+                None => continue,
+            };
+
+            let mut action = format!("Ran {} for the trigger {}", command, trigger);
+
+            if skipped > 0 {
+                action.push_str(&format!(
+                    ", after skipping {} matches over the rate limit",
+                    skipped
+                ));
+            }
+
+            log!("{}", action);
+
+            if let Err(e) = self.context.store.audit("trigger", &action) {
+                log!("Failed to write to the audit log: {}", e);
+            }
+
+            // The command is sent from another task, as the game process may be waiting for
+            // this one to take its output
+            let handler = handler.clone();
+            tokio::spawn(async move { handler.send_command(command).await });
+        }
+    }
+
+    async fn handle_stop(&self) {
+        self.end_match(None, None).await;
+
+        // Players are not always logged leaving when the server stops
+        let online_players = mem::take(&mut *self.context.online_players.lock().await);
+
+        for player in online_players {
+            match self.context.store.end_session(None, &player) {
+                Ok(_) => (),
+                Err(e) => log!("Failed to record the session of {}: {}", player, e),
+            }
+        }
+    }
+
+    async fn end_match(&self, wave: Option<u32>, winner: Option<&str>) {
+        if let Some(id) = self.context.current_match.lock().await.take() {
+            match self.context.store.end_match(id, wave, winner) {
+                Ok(_) => (),
+                Err(e) => log!("Failed to record the end of the match: {}", e),
+            }
+        }
+    }
+
+    pub async fn handle_input(self, input_sender: mpsc::Sender<Input>) -> JoinHandle<()> {
         let input_handler = tokio::spawn(async move {
             if self.webhook == 0 {
-                println!("Running in longpoll mode");
+                info!("Running in longpoll mode");
                 LongPoll::new(
                     self.api.clone(),
                     BotUpdateHandler::new(self.api, input_sender, self.context),
@@ -85,7 +523,7 @@ impl BotInstance {
                 .run()
                 .await;
             } else {
-                println!("Running at port {} in webhook mode", self.webhook);
+                info!("Running at port {} in webhook mode", self.webhook);
                 match webhook::run_server(
                     ([127, 0, 0, 1], self.webhook),
                     "/",
@@ -95,7 +533,7 @@ impl BotInstance {
                 {
                     Ok(_) => (),
                     Err(e) => {
-                        eprintln!("Failed running the webhook server: {}", e.to_string());
+                        log!("Failed running the webhook server: {}", e);
                         process::exit(1);
                     }
                 }
@@ -103,25 +541,201 @@ impl BotInstance {
         });
         input_handler
     }
+
+    pub async fn handle_status(self, input_sender: mpsc::Sender<Input>) -> JoinHandle<()> {
+        let status_handler = tokio::spawn(async move {
+            if !self.status_chats.is_empty() {
+                status::run(
+                    BotUpdateHandler::new(self.api, input_sender, self.context),
+                    self.status_chats,
+                    self.status_interval,
+                )
+                .await;
+            }
+        });
+        status_handler
+    }
 }
 
+// Hosts the next map of the playlist, if there is any
+async fn host_next(handler: BotUpdateHandler, is_hosted: bool) {
+    match playlist::host_next(&handler, is_hosted).await {
+        Ok(_) => (),
+        Err(e) => log!("Failed to host the next map of the playlist: {}", e),
+    }
+}
+
+type CommandList = Arc<HashMap<String, GameCommand>>;
+
+// The map lists shown by the map picker, keyed by the chat and the id of the message
+type MapLists = HashMap<(i64, Integer), Vec<String>>;
+
 pub struct Context {
     pub user: String,
     pub bot_username: Arc<RwLock<Option<String>>>,
-    pub commands: Arc<RwLock<Option<Arc<HashMap<String, GameCommand>>>>>,
+    pub commands: Arc<RwLock<Option<CommandList>>>,
     pub output_chat: Arc<Mutex<HashSet<i64>>>,
+    pub output_format: Arc<Mutex<HashMap<i64, OutputFormat>>>,
+    pub default_format: OutputFormat,
     pub bot_commands_sent: Arc<RwLock<bool>>,
+    pub callbacks: Arc<HashMap<String, CallbackHandler>>,
+    pub maps: Arc<Mutex<MapLists>>,
+    pub confirm_commands: Arc<HashSet<String>>,
+    pub confirm_timeout: Duration,
+    // Commands linked users may run with their own player as the target
+    pub self_commands: Arc<HashSet<String>>,
+    // Keyed by the chat and the id of the message asking for confirmation
+    pub confirmations: Arc<Mutex<HashMap<(i64, Integer), Confirmation>>>,
+    // The mode of the map last hosted, as `status` does not report it
+    pub mode: Arc<Mutex<Option<String>>>,
+    // The player list last shown by `/players`
+    pub players: Arc<Mutex<Vec<Player>>>,
+    // The group whose messages are relayed to and from the in-game chat
+    pub bridge_chat: Option<i64>,
+    pub store: Store,
+    // The private chat with the user who can interact with this bot
+    pub admin_chat: Arc<Mutex<Option<i64>>>,
+    // The codes given out by `/link`
+    pub link_codes: Arc<Mutex<HashMap<String, PendingLink>>>,
+    // The names of the players in game, from the join and leave events
+    pub online_players: Arc<Mutex<HashSet<String>>>,
+    // The id of the match in progress in the store
+    pub current_match: Arc<Mutex<Option<i64>>>,
+    pub player_joined: Arc<Notify>,
+    pub vote_duration: Duration,
+    // The `/votemap` poll that is open, if any
+    pub map_vote: Arc<Mutex<Option<MapVote>>>,
+    // The map that won a vote during a game, hosted instead of the playlist after the game over
+    pub next_map: Arc<Mutex<Option<PlaylistEntry>>>,
+    pub alert_chats: Arc<Vec<i64>>,
+    pub scrollback: Scrollback,
+    // The output rules given on the command line, which cannot be removed with `/rules`
+    pub config_rules: Arc<Vec<Rule>>,
+    // The command line rules followed by the stored ones, in the order they are tried
+    pub rules: Arc<RwLock<Vec<Rule>>>,
+    // The triggers given on the command line, which cannot be removed with `/triggers`
+    pub config_triggers: Arc<Vec<Trigger>>,
+    pub triggers: Arc<RwLock<Vec<Trigger>>>,
+    pub trigger_limit: Arc<Mutex<RateLimit>>,
 }
 
 impl Context {
-    fn init(user_id: String) -> Self {
-        Self {
-            user: user_id,
+    fn init(config: &Config) -> Result<Self, String> {
+        let store = Store::open(&config.data)?;
+
+        let admin_chat = store
+            .setting("admin_chat")?
+            .and_then(|chat_id| chat_id.parse().ok());
+
+        // The formats chosen with `/format` before the last restart
+        let output_format = store
+            .chat_formats()?
+            .into_iter()
+            .filter_map(|(chat_id, format)| Some((chat_id, format.parse().ok()?)))
+            .collect();
+
+        let rules = rules::load(&config.rules, &store)?;
+        let triggers = trigger::load(&config.triggers, &store)?;
+
+        Ok(Self {
+            user: config.user.clone(),
             bot_username: Arc::new(RwLock::new(None)),
             commands: Arc::new(RwLock::new(None)),
             output_chat: Arc::new(Mutex::new(HashSet::new())),
+            output_format: Arc::new(Mutex::new(output_format)),
+            default_format: config.format,
             bot_commands_sent: Arc::new(RwLock::new(false)),
+            callbacks: Arc::new(CallbackMap::init()),
+            maps: Arc::new(Mutex::new(HashMap::new())),
+            confirm_commands: Arc::new(config.confirm_commands.clone()),
+            confirm_timeout: config.confirm_timeout,
+            self_commands: Arc::new(config.self_commands.clone()),
+            confirmations: Arc::new(Mutex::new(HashMap::new())),
+            mode: Arc::new(Mutex::new(None)),
+            players: Arc::new(Mutex::new(Vec::new())),
+            bridge_chat: config.bridge_chat,
+            store,
+            admin_chat: Arc::new(Mutex::new(admin_chat)),
+            link_codes: Arc::new(Mutex::new(HashMap::new())),
+            online_players: Arc::new(Mutex::new(HashSet::new())),
+            current_match: Arc::new(Mutex::new(None)),
+            player_joined: Arc::new(Notify::new()),
+            vote_duration: config.vote_duration,
+            map_vote: Arc::new(Mutex::new(None)),
+            next_map: Arc::new(Mutex::new(None)),
+            alert_chats: Arc::new(config.alert_chats.clone()),
+            scrollback: Scrollback::new(config.scrollback),
+            config_rules: Arc::new(config.rules.clone()),
+            rules: Arc::new(RwLock::new(rules)),
+            config_triggers: Arc::new(config.triggers.clone()),
+            triggers: Arc::new(RwLock::new(triggers)),
+            trigger_limit: Arc::new(Mutex::new(RateLimit::new(
+                config.trigger_cooldown,
+                config.trigger_limit,
+            ))),
+        })
+    }
+
+    // Picks up the rules added or removed with `/rules`
+    pub async fn reload_rules(&self) -> Result<(), String> {
+        *self.rules.write().await = rules::load(&self.config_rules, &self.store)?;
+        Ok(())
+    }
+
+    // Picks up the triggers added or removed with `/triggers`
+    pub async fn reload_triggers(&self) -> Result<(), String> {
+        *self.triggers.write().await = trigger::load(&self.config_triggers, &self.store)?;
+        Ok(())
+    }
+
+    pub async fn format_of(&self, chat_id: i64) -> OutputFormat {
+        self.output_format
+            .lock()
+            .await
+            .get(&chat_id)
+            .copied()
+            .unwrap_or(self.default_format)
+    }
+
+    // The chats the output is sent to, with the format of each
+    pub async fn output_targets(&self) -> Vec<(i64, OutputFormat)> {
+        let output_chat = self.output_chat.lock().await;
+        let output_format = self.output_format.lock().await;
+
+        output_chat
+            .iter()
+            .map(|chat_id| {
+                let format = output_format
+                    .get(chat_id)
+                    .copied()
+                    .unwrap_or(self.default_format);
+                (*chat_id, format)
+            })
+            .collect()
+    }
+
+    // The chats alerts about the game server are sent to. Without alert chats configured, they go
+    // to the admin's private chat and the output chats
+    pub async fn alert_targets(&self) -> Vec<i64> {
+        if !self.alert_chats.is_empty() {
+            return self.alert_chats.to_vec();
         }
+
+        let mut chats = self
+            .output_chat
+            .lock()
+            .await
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+
+        if let Some(admin_chat) = *self.admin_chat.lock().await {
+            if !chats.contains(&admin_chat) {
+                chats.push(admin_chat);
+            }
+        }
+
+        chats
     }
 }
 
@@ -132,25 +746,206 @@ impl Clone for Context {
             bot_username: Arc::clone(&self.bot_username),
             commands: Arc::clone(&self.commands),
             output_chat: Arc::clone(&self.output_chat),
+            output_format: Arc::clone(&self.output_format),
+            default_format: self.default_format,
             bot_commands_sent: Arc::clone(&self.bot_commands_sent),
+            callbacks: Arc::clone(&self.callbacks),
+            maps: Arc::clone(&self.maps),
+            confirm_commands: Arc::clone(&self.confirm_commands),
+            confirm_timeout: self.confirm_timeout,
+            self_commands: Arc::clone(&self.self_commands),
+            confirmations: Arc::clone(&self.confirmations),
+            mode: Arc::clone(&self.mode),
+            players: Arc::clone(&self.players),
+            bridge_chat: self.bridge_chat,
+            store: self.store.clone(),
+            admin_chat: Arc::clone(&self.admin_chat),
+            link_codes: Arc::clone(&self.link_codes),
+            online_players: Arc::clone(&self.online_players),
+            current_match: Arc::clone(&self.current_match),
+            player_joined: Arc::clone(&self.player_joined),
+            vote_duration: self.vote_duration,
+            map_vote: Arc::clone(&self.map_vote),
+            next_map: Arc::clone(&self.next_map),
+            alert_chats: Arc::clone(&self.alert_chats),
+            scrollback: self.scrollback.clone(),
+            config_rules: Arc::clone(&self.config_rules),
+            rules: Arc::clone(&self.rules),
+            config_triggers: Arc::clone(&self.config_triggers),
+            triggers: Arc::clone(&self.triggers),
+            trigger_limit: Arc::clone(&self.trigger_limit),
         }
     }
 }
 
 pub struct BotUpdateHandler {
     pub api: Api,
-    pub input_sender: mpsc::Sender<String>,
+    pub input_sender: mpsc::Sender<Input>,
     pub context: Context,
 }
 
 impl BotUpdateHandler {
-    fn new(api: Api, input_sender: mpsc::Sender<String>, context: Context) -> Self {
+    fn new(api: Api, input_sender: mpsc::Sender<Input>, context: Context) -> Self {
         Self {
             api,
             input_sender,
             context,
         }
     }
+
+    pub async fn send_command(&self, command: String) {
+        let mut args = command.split_whitespace();
+
+        if args.next() == Some("host") {
+            let mode = args.nth(1).unwrap_or("survival");
+            *self.context.mode.lock().await = Some(mode.to_owned());
+        }
+
+        let input = Input {
+            command,
+            response_sender: None,
+        };
+
+        match self.input_sender.send(input).await {
+            Ok(_) => (),
+            Err(e) => {
+                log!("Failed to communicate with the game proccess: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Runs a command and returns its response instead of sending it to the output chats
+    pub async fn query(&self, command: String) -> Option<String> {
+        let (response_sender, response_receiver) = oneshot::channel();
+
+        let input = Input {
+            command,
+            response_sender: Some(response_sender),
+        };
+
+        match self.input_sender.send(input).await {
+            Ok(_) => (),
+            Err(e) => {
+                log!("Failed to communicate with the game proccess: {}", e);
+                process::exit(1);
+            }
+        }
+
+        time::timeout(QUERY_TIMEOUT, response_receiver)
+            .await
+            .ok()?
+            .ok()
+    }
+
+    // The private chat with the admin is only known after they talk to the bot there
+    async fn remember_admin_chat(&self, chat_id: i64) {
+        let mut admin_chat = self.context.admin_chat.lock().await;
+
+        if *admin_chat != Some(chat_id) {
+            match self
+                .context
+                .store
+                .set_setting("admin_chat", &chat_id.to_string())
+            {
+                Ok(_) => *admin_chat = Some(chat_id),
+                Err(e) => log!("Failed to save the admin chat: {}", e),
+            }
+        }
+    }
+
+    // Asks the user to confirm the console command before it is run
+    pub async fn request_confirmation(
+        &self,
+        chat_id: i64,
+        confirmation: Confirmation,
+    ) -> Result<(), ExecuteError> {
+        let command = confirmation.command.clone();
+
+        let keyboard = vec![vec![
+            InlineKeyboardButton::with_callback_data("Confirm", "confirm:yes"),
+            InlineKeyboardButton::with_callback_data("Cancel", "confirm:no"),
+        ]];
+
+        let send_message = SendMessage::new(chat_id, format!("Run \"{}\"?", command))
+            .reply_markup(InlineKeyboardMarkup::from(keyboard));
+        let message = self.api.execute(send_message).await?;
+
+        self.context
+            .confirmations
+            .lock()
+            .await
+            .insert((chat_id, message.id), confirmation);
+
+        let handler = self.clone();
+
+        tokio::spawn(async move {
+            time::sleep(handler.context.confirm_timeout).await;
+
+            let confirmation = handler
+                .context
+                .confirmations
+                .lock()
+                .await
+                .remove(&(chat_id, message.id));
+
+            if confirmation.is_some() {
+                let edit_message_text = EditMessageText::new(
+                    chat_id,
+                    message.id,
+                    format!("Confirmation of \"{}\" expired", command),
+                );
+
+                match handler.api.execute(edit_message_text).await {
+                    Ok(_) => (),
+                    Err(e) => log!("{}", e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn handle_update(self, kind: UpdateKind) {
+        match kind {
+            UpdateKind::CallbackQuery(query) => match self.handle_callback_query(query).await {
+                Ok(_) => (),
+                Err(e) => log!("{}", e),
+            },
+            // Telegram closes the poll once its open period is over
+            UpdateKind::Poll(Poll::Regular(poll)) if poll.is_closed => {
+                vote::close(&self, &poll.id).await
+            }
+            UpdateKind::PollAnswer(answer) => vote::record_answer(&self, answer).await,
+            _ => (),
+        }
+    }
+
+    async fn handle_callback_query(self, query: CallbackQuery) -> Result<(), ExecuteError> {
+        if query.from.username.as_ref() != Some(&self.context.user) {
+            let answer_callback_query =
+                AnswerCallbackQuery::new(query.id).text("You are not allowed to do this");
+            self.api.execute(answer_callback_query).await?;
+            return Ok(());
+        }
+
+        let mut args = query
+            .data
+            .as_deref()
+            .unwrap_or_default()
+            .split(':')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let name = args.remove(0);
+
+        match Arc::clone(&self.context.callbacks).get(&name) {
+            Some(callback) => callback(self, query, args).await,
+            None => {
+                self.api.execute(AnswerCallbackQuery::new(query.id)).await?;
+                Ok(())
+            }
+        }
+    }
 }
 
 impl Clone for BotUpdateHandler {
@@ -170,11 +965,24 @@ impl UpdateHandler for BotUpdateHandler {
         let handler = self.clone();
 
         Box::pin(async move {
+            // The updates other than messages are handled on their own
+            match update.kind {
+                UpdateKind::Message(_) => (),
+                kind => return handler.handle_update(kind).await,
+            }
+
             if let UpdateKind::Message(message) = update.kind {
+                if Some(message.get_chat_id()) == handler.context.bridge_chat {
+                    if let Some(say) = bridge::relay(&message) {
+                        handler.send_command(say).await;
+                        return;
+                    }
+                }
+
                 if let Ok(command) = Command::try_from(message) {
                     let bot_commands_sent = Arc::clone(&handler.context.bot_commands_sent);
 
-                    let is_bot_commands_set = bot_commands_sent.read().await.clone();
+                    let is_bot_commands_set = *bot_commands_sent.read().await;
                     if !is_bot_commands_set {
                         let commands = Arc::clone(
                             Arc::clone(&handler.context.commands)
@@ -186,20 +994,16 @@ impl UpdateHandler for BotUpdateHandler {
                         let command_list = commands
                             .iter()
                             .sorted_unstable_by(|a, b| Ord::cmp(&a.0, &b.0))
-                            .map(|(name, command)| {
+                            .flat_map(|(name, command)| {
                                 BotCommand::new(name, command.description.clone())
                             })
-                            .flat_map(|command| command)
                             .filter(|command| command.name() != "/start");
 
                         let set_my_commands = SetMyCommands::new(command_list);
                         match handler.api.execute(set_my_commands).await {
                             Ok(_) => (),
                             Err(e) => {
-                                eprintln!(
-                                    "Failed sending the command list to Telegram: {}",
-                                    e.to_string()
-                                );
+                                log!("Failed sending the command list to Telegram: {}", e);
                                 process::exit(1);
                             }
                         }
@@ -215,10 +1019,7 @@ impl UpdateHandler for BotUpdateHandler {
                         let bot = match handler.api.execute(GetMe).await {
                             Ok(b) => b,
                             Err(e) => {
-                                eprintln!(
-                                    "Failed to get bot info from Telegram: {}",
-                                    e.to_string()
-                                );
+                                log!("Failed to get bot info from Telegram: {}", e);
                                 process::exit(1);
                             }
                         };
@@ -227,10 +1028,42 @@ impl UpdateHandler for BotUpdateHandler {
                         *bot_username = Some(bot.username);
                     }
 
+                    let commands = Arc::clone(
+                        Arc::clone(&handler.context.commands)
+                            .read()
+                            .await
+                            .as_ref()
+                            .unwrap(),
+                    );
+                    let game_command = commands.get(command.get_name());
+                    let is_public = game_command.is_some_and(|command| command.is_public);
+
                     let mut ignore_message = true;
 
                     if let Some(user) = command.get_message().get_user() {
-                        if user.username.as_ref() == Some(&handler.context.user) {
+                        let is_admin = user.username.as_ref() == Some(&handler.context.user);
+
+                        if is_admin {
+                            if let MessageKind::Private { .. } = command.get_message().kind {
+                                handler
+                                    .remember_admin_chat(command.get_message().get_chat_id())
+                                    .await;
+                            }
+                        }
+
+                        // Other users may run some commands on the player linked to them
+                        let is_on_own_player = !is_admin
+                            && !is_public
+                            && handler
+                                .context
+                                .self_commands
+                                .contains(command.get_name().trim_start_matches('/'))
+                            && matches!(
+                                handler.context.store.link(user.id),
+                                Ok(Some(link)) if link::targets_own_player(&link, command.get_args())
+                            );
+
+                        if is_admin || is_public || is_on_own_player {
                             if matches!(command.get_message().kind, MessageKind::Group { .. })
                                 || matches!(
                                     command.get_message().kind,
@@ -254,18 +1087,10 @@ impl UpdateHandler for BotUpdateHandler {
                     }
 
                     if !ignore_message {
-                        let commands = Arc::clone(
-                            Arc::clone(&handler.context.commands)
-                                .read()
-                                .await
-                                .as_ref()
-                                .unwrap(),
-                        );
-
-                        if let Some(game_command) = commands.get(command.get_name()) {
+                        if let Some(game_command) = game_command {
                             match (game_command.handler)(handler, command).await {
                                 Ok(_) => (),
-                                Err(e) => eprintln!("{}", e.to_string()),
+                                Err(e) => log!("{}", e),
                             }
                         }
                     }
@@ -0,0 +1,43 @@
+use tgbot::types::Message;
+
+// Builds the `say` command that relays a plain text message of the bridged group into the game
+pub fn relay(message: &Message) -> Option<String> {
+    let user = message.get_user()?;
+    let text = &message.get_text()?.data;
+
+    if user.is_bot || text.starts_with('/') {
+        return None;
+    }
+
+    let name = sanitize(&user.get_full_name());
+    let text = sanitize(text);
+
+    if text.is_empty() {
+        return None;
+    }
+
+    Some(format!("say [TG] {}: {}", name, text))
+}
+
+// The text is written to the console as a single line, so a line break could start another
+// command. Square brackets are escaped so the text can not carry colour tags into the game
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('[', "[[")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_line_breaks_and_tags() {
+        assert_eq!(sanitize("hi\nstop\r\n\tnow"), "hi stop now");
+        assert_eq!(sanitize("[red]red"), "[[red]red");
+    }
+}
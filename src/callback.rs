@@ -0,0 +1,565 @@
+use crate::{bot::BotUpdateHandler, chunk, logfile, parse, tempban};
+use futures_util::future::BoxFuture;
+use itertools::Itertools;
+use std::{collections::HashMap, io::Cursor};
+use tgbot::{
+    methods::{AnswerCallbackQuery, EditMessageText, SendDocument, SendMessage},
+    types::{
+        CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputFileInfo,
+        InputFileReader, Integer,
+    },
+    ExecuteError,
+};
+
+const OUTCOME_LIMIT: usize = 2048;
+
+// The most buttons Telegram accepts in an inline keyboard
+pub const KEYBOARD_LIMIT: usize = 100;
+
+pub const GAME_MODES: [&str; 4] = ["survival", "attack", "pvp", "sandbox"];
+
+pub type CallbackHandler = Box<
+    dyn Fn(
+            BotUpdateHandler,
+            CallbackQuery,
+            Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>>
+        + Send
+        + Sync,
+>;
+
+// A console command waiting to be confirmed by the user who sent it
+pub struct Confirmation {
+    pub command: String,
+    pub user_id: Integer,
+    // Recorded before the command is run, for `/tempban`
+    pub tempban: Option<tempban::Request>,
+    // The player list the command was chosen from, which shows the outcome once it is run
+    pub player_list: Option<Integer>,
+}
+
+pub struct CallbackMap;
+
+// Sends a log file as a document, or what went wrong as a message
+pub async fn send_log(
+    handler: &BotUpdateHandler,
+    chat_id: i64,
+    name: &str,
+) -> Result<(), ExecuteError> {
+    match logfile::read(name) {
+        Ok(log) => {
+            let document = InputFileReader::new(Cursor::new(log)).info(InputFileInfo::new(name));
+
+            let send_document = SendDocument::new(chat_id, InputFile::reader(document));
+            handler.api.execute(send_document).await?;
+        }
+        Err(e) => {
+            handler.api.execute(SendMessage::new(chat_id, e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Lists the players in game with buttons to moderate each of them, below the outcome of the last
+// action if there is one
+pub async fn player_list(
+    handler: &BotUpdateHandler,
+    outcome: Option<String>,
+) -> (String, Option<InlineKeyboardMarkup>) {
+    let players = match handler.query(String::from("players")).await {
+        Some(output) => parse::players(&output),
+        None => return (String::from("The server did not respond"), None),
+    };
+
+    let mut text = outcome.map(|outcome| outcome + "\n\n").unwrap_or_default();
+
+    if players.is_empty() {
+        text.push_str("No players are in game");
+        *handler.context.players.lock().await = players;
+        return (text, None);
+    }
+
+    text.push_str(&format!("Players: {}", players.len()));
+
+    // Each player takes a row for the name and one for the actions
+    let shown = players.len().min(KEYBOARD_LIMIT / 4);
+
+    if shown < players.len() {
+        text.push_str(&format!("\nOnly the first {} have buttons", shown));
+    }
+
+    let keyboard = players
+        .iter()
+        .take(shown)
+        .flat_map(|player| {
+            let name = if player.admin {
+                format!("{} (admin)", player.name)
+            } else {
+                player.name.clone()
+            };
+            let admin = if player.admin {
+                "Remove admin"
+            } else {
+                "Make admin"
+            };
+
+            vec![
+                vec![InlineKeyboardButton::with_callback_data(
+                    name,
+                    format!("player:info:{}", player.uuid),
+                )],
+                vec![
+                    InlineKeyboardButton::with_callback_data(
+                        "Kick",
+                        format!("player:kick:{}", player.uuid),
+                    ),
+                    InlineKeyboardButton::with_callback_data(
+                        "Ban",
+                        format!("player:ban:{}", player.uuid),
+                    ),
+                    InlineKeyboardButton::with_callback_data(
+                        admin,
+                        format!("player:admin:{}", player.uuid),
+                    ),
+                ],
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    *handler.context.players.lock().await = players;
+
+    (text, Some(InlineKeyboardMarkup::from(keyboard)))
+}
+
+// Runs a command chosen from a player list and shows its outcome above the refreshed list
+async fn run_from_player_list(
+    handler: &BotUpdateHandler,
+    chat_id: i64,
+    message_id: Integer,
+    command: String,
+) -> Result<(), ExecuteError> {
+    let outcome = match handler.query(command).await {
+        // Leave room for the player list in the message
+        Some(output) if !output.trim().is_empty() => chunk::split(output.trim(), OUTCOME_LIMIT)
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        Some(_) => String::from("Done"),
+        None => String::from("The server did not respond"),
+    };
+
+    let (text, keyboard) = player_list(handler, Some(outcome)).await;
+
+    let mut edit_message_text = EditMessageText::new(chat_id, message_id, text);
+
+    if let Some(keyboard) = keyboard {
+        edit_message_text = edit_message_text.reply_markup(keyboard);
+    }
+
+    handler.api.execute(edit_message_text).await?;
+
+    Ok(())
+}
+
+impl CallbackMap {
+    // Callback data has the form of `name:arg1:arg2...`, where `name` is the key of the handler
+    pub fn init() -> HashMap<String, CallbackHandler> {
+        let mut callbacks = HashMap::new();
+
+        fn cancel(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            _args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                if let Some(message) = &query.message {
+                    let key = (message.get_chat_id(), message.id);
+                    handler.context.maps.lock().await.remove(&key);
+
+                    let edit_message_text =
+                        EditMessageText::new(message.get_chat_id(), message.id, "Cancelled");
+                    handler.api.execute(edit_message_text).await?;
+                }
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                Ok(())
+            })
+        }
+        callbacks.insert(String::from("cancel"), Box::new(cancel) as CallbackHandler);
+
+        fn host_map(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let index = args.first().and_then(|index| index.parse::<usize>().ok());
+                let map = match (&query.message, index) {
+                    (Some(message), Some(index)) => handler
+                        .context
+                        .maps
+                        .lock()
+                        .await
+                        .get(&(message.get_chat_id(), message.id))
+                        .and_then(|maps| maps.get(index))
+                        .cloned(),
+                    _ => None,
+                };
+
+                let (message, map) = match (&query.message, map) {
+                    (Some(message), Some(map)) => (message, map),
+                    _ => {
+                        let answer_callback_query =
+                            AnswerCallbackQuery::new(query.id).text("This map list has expired");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                };
+
+                let mut keyboard = GAME_MODES
+                    .iter()
+                    .map(|mode| {
+                        InlineKeyboardButton::with_callback_data(
+                            *mode,
+                            format!("host_mode:{}:{}", index.unwrap_or_default(), mode),
+                        )
+                    })
+                    .chunks(2)
+                    .into_iter()
+                    .map(|row| row.collect())
+                    .collect::<Vec<_>>();
+                keyboard.push(vec![InlineKeyboardButton::with_callback_data(
+                    "Cancel", "cancel",
+                )]);
+
+                let edit_message_text = EditMessageText::new(
+                    message.get_chat_id(),
+                    message.id,
+                    format!("Map: {}\nChoose a mode", map),
+                )
+                .reply_markup(InlineKeyboardMarkup::from(keyboard));
+                handler.api.execute(edit_message_text).await?;
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                Ok(())
+            })
+        }
+        callbacks.insert(
+            String::from("host_map"),
+            Box::new(host_map) as CallbackHandler,
+        );
+
+        fn host_mode(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let index = args.first().and_then(|index| index.parse::<usize>().ok());
+                let map = match (&query.message, index) {
+                    // The map list is done with once a mode is chosen
+                    (Some(message), Some(index)) => handler
+                        .context
+                        .maps
+                        .lock()
+                        .await
+                        .remove(&(message.get_chat_id(), message.id))
+                        .and_then(|maps| maps.get(index).cloned()),
+                    _ => None,
+                };
+                let mode = args
+                    .get(1)
+                    .filter(|mode| GAME_MODES.contains(&mode.as_str()));
+
+                let (message, map, mode) = match (&query.message, map, mode) {
+                    (Some(message), Some(map), Some(mode)) => (message, map, mode),
+                    _ => {
+                        let answer_callback_query =
+                            AnswerCallbackQuery::new(query.id).text("This map list has expired");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                };
+
+                let edit_message_text = EditMessageText::new(
+                    message.get_chat_id(),
+                    message.id,
+                    format!("Hosting {} in {} mode", map, mode),
+                );
+                handler.api.execute(edit_message_text).await?;
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                handler.send_command(format!("host {} {}", map, mode)).await;
+
+                Ok(())
+            })
+        }
+        callbacks.insert(
+            String::from("host_mode"),
+            Box::new(host_mode) as CallbackHandler,
+        );
+
+        fn confirm(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let is_confirmed = args.first().map(String::as_str) == Some("yes");
+
+                let message = match &query.message {
+                    Some(message) => message,
+                    None => {
+                        handler
+                            .api
+                            .execute(AnswerCallbackQuery::new(query.id))
+                            .await?;
+                        return Ok(());
+                    }
+                };
+                let key = (message.get_chat_id(), message.id);
+
+                let confirmation = {
+                    let mut confirmations = handler.context.confirmations.lock().await;
+
+                    match confirmations.remove(&key) {
+                        Some(confirmation) if confirmation.user_id != query.from.id => {
+                            confirmations.insert(key, confirmation);
+                            None
+                        }
+                        Some(confirmation) => Some(confirmation),
+                        None => {
+                            let answer_callback_query = AnswerCallbackQuery::new(query.id)
+                                .text("This confirmation has expired");
+                            handler.api.execute(answer_callback_query).await?;
+                            return Ok(());
+                        }
+                    }
+                };
+
+                let confirmation = match confirmation {
+                    Some(confirmation) => confirmation,
+                    None => {
+                        let answer_callback_query = AnswerCallbackQuery::new(query.id)
+                            .text("Only the user who sent the command can confirm it");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                };
+
+                let text = if is_confirmed {
+                    format!("Confirmed \"{}\"", confirmation.command)
+                } else {
+                    format!("Cancelled \"{}\"", confirmation.command)
+                };
+
+                let edit_message_text = EditMessageText::new(key.0, key.1, text);
+                handler.api.execute(edit_message_text).await?;
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                if is_confirmed {
+                    match (confirmation.tempban, confirmation.player_list) {
+                        (Some(request), _) => {
+                            let text = tempban::apply(&handler, request).await;
+                            handler.api.execute(SendMessage::new(key.0, text)).await?;
+                        }
+                        (None, Some(message_id)) => {
+                            run_from_player_list(&handler, key.0, message_id, confirmation.command)
+                                .await?;
+                        }
+                        (None, None) => handler.send_command(confirmation.command).await,
+                    }
+                }
+
+                Ok(())
+            })
+        }
+        callbacks.insert(
+            String::from("confirm"),
+            Box::new(confirm) as CallbackHandler,
+        );
+
+        fn player(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let player = match args.get(1) {
+                    Some(uuid) => handler
+                        .context
+                        .players
+                        .lock()
+                        .await
+                        .iter()
+                        .find(|player| &player.uuid == uuid)
+                        .cloned(),
+                    None => None,
+                };
+
+                let (message, player) = match (&query.message, player) {
+                    (Some(message), Some(player)) => (message, player),
+                    _ => {
+                        let answer_callback_query =
+                            AnswerCallbackQuery::new(query.id).text("This player list has expired");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                };
+
+                let command = match args.first().map(String::as_str) {
+                    Some("info") => format!("info {}", player.uuid),
+                    // The name may have color tags the console does not match, the UUID has none
+                    Some("kick") => format!("kick {}", player.uuid),
+                    Some("ban") => format!("ban id {}", player.uuid),
+                    Some("admin") if player.admin => format!("admin remove {}", player.uuid),
+                    Some("admin") => format!("admin add {}", player.uuid),
+                    _ => {
+                        handler
+                            .api
+                            .execute(AnswerCallbackQuery::new(query.id))
+                            .await?;
+                        return Ok(());
+                    }
+                };
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                let name = command.split_whitespace().next().unwrap_or_default();
+                if handler.context.confirm_commands.contains(name) {
+                    let confirmation = Confirmation {
+                        command,
+                        user_id: query.from.id,
+                        tempban: None,
+                        player_list: Some(message.id),
+                    };
+                    return handler
+                        .request_confirmation(message.get_chat_id(), confirmation)
+                        .await;
+                }
+
+                run_from_player_list(&handler, message.get_chat_id(), message.id, command).await
+            })
+        }
+        callbacks.insert(String::from("player"), Box::new(player) as CallbackHandler);
+
+        fn join(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let is_approved = args.first().map(String::as_str) == Some("approve");
+                let id = args.get(1).and_then(|id| id.parse::<i64>().ok());
+                let decided_by = query.from.username.clone().unwrap_or_default();
+
+                let request = match id {
+                    Some(id) => {
+                        handler
+                            .context
+                            .store
+                            .decide_join_request(id, is_approved, &decided_by)
+                    }
+                    None => Ok(None),
+                };
+
+                let (message, request) = match (&query.message, request) {
+                    (Some(message), Ok(Some(request))) => (message, request),
+                    (_, Err(e)) => {
+                        log!("Failed to save the decision on the join request: {}", e);
+                        let answer_callback_query =
+                            AnswerCallbackQuery::new(query.id).text("Failed to save the decision");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                    _ => {
+                        let answer_callback_query = AnswerCallbackQuery::new(query.id)
+                            .text("This request has already been decided");
+                        handler.api.execute(answer_callback_query).await?;
+                        return Ok(());
+                    }
+                };
+
+                if is_approved {
+                    handler
+                        .send_command(format!("whitelist add {}", request.uuid))
+                        .await;
+                }
+
+                let decision = if is_approved { "approved" } else { "denied" };
+
+                let edit_message_text = EditMessageText::new(
+                    message.get_chat_id(),
+                    message.id,
+                    format!(
+                        "{} asked to join the server\nPlayer: {}\nUUID: {}\n\nRequest {} by @{}",
+                        request.username, request.name, request.uuid, decision, decided_by
+                    ),
+                );
+                handler.api.execute(edit_message_text).await?;
+
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                let send_message = SendMessage::new(
+                    request.chat_id,
+                    format!(
+                        "{}, your request to join as {} was {}",
+                        request.username, request.name, decision
+                    ),
+                );
+                handler.api.execute(send_message).await?;
+
+                Ok(())
+            })
+        }
+        callbacks.insert(String::from("join"), Box::new(join) as CallbackHandler);
+
+        fn log_file(
+            handler: BotUpdateHandler,
+            query: CallbackQuery,
+            args: Vec<String>,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                handler
+                    .api
+                    .execute(AnswerCallbackQuery::new(query.id))
+                    .await?;
+
+                if let (Some(message), Some(name)) = (&query.message, args.first()) {
+                    send_log(&handler, message.get_chat_id(), name).await?;
+                }
+
+                Ok(())
+            })
+        }
+        callbacks.insert(
+            String::from("log_file"),
+            Box::new(log_file) as CallbackHandler,
+        );
+
+        callbacks
+    }
+}
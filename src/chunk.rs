@@ -0,0 +1,137 @@
+// Telegram measures the message length in UTF-16 code units after entity parsing
+pub const MESSAGE_LIMIT: usize = 4096;
+
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+pub struct Chunker {
+    limit: usize,
+    chunk: String,
+    chunk_len: usize,
+}
+
+impl Chunker {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            chunk: String::new(),
+            chunk_len: 0,
+        }
+    }
+
+    // Appends a line and returns the chunks that can not grow any further. A chunk only ends at
+    // a line boundary, unless the line is longer than the limit by itself
+    pub fn push(&mut self, line: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let line_len = utf16_len(line);
+
+        if self.chunk_len + line_len > self.limit {
+            chunks.extend(self.finish());
+        }
+
+        if line_len > self.limit {
+            for c in line.chars() {
+                let c_len = c.len_utf16();
+
+                if self.chunk_len + c_len > self.limit {
+                    chunks.extend(self.finish());
+
+                    // The line ends with the chunk, so its newline is not carried into the next one
+                    if c == '\n' {
+                        continue;
+                    }
+                }
+
+                self.chunk.push(c);
+                self.chunk_len += c_len;
+            }
+        } else {
+            self.chunk.push_str(line);
+            self.chunk_len += line_len;
+        }
+
+        chunks
+    }
+
+    // Telegram rejects empty messages, so a chunk of only whitespace is left out
+    pub fn finish(&mut self) -> Option<String> {
+        self.chunk_len = 0;
+        let chunk = std::mem::take(&mut self.chunk);
+
+        if chunk.trim().is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+pub fn split(text: &str, limit: usize) -> Vec<String> {
+    let mut chunker = Chunker::new(limit);
+
+    let mut chunks = text
+        .split_inclusive('\n')
+        .flat_map(|line| chunker.push(line))
+        .collect::<Vec<_>>();
+    chunks.extend(chunker.finish());
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_utf16() {
+        assert_eq!(utf16_len("abc"), 3);
+        assert_eq!(utf16_len("玩家"), 2);
+        assert_eq!(utf16_len("🙂"), 2);
+    }
+
+    #[test]
+    fn keeps_short_text_together() {
+        assert_eq!(split("a\nb\nc\n", 10), vec!["a\nb\nc\n"]);
+        assert!(split("", 10).is_empty());
+    }
+
+    #[test]
+    fn splits_at_line_boundaries() {
+        assert_eq!(split("aaa\nbbb\nccc\n", 8), vec!["aaa\nbbb\n", "ccc\n"]);
+        assert_eq!(split("aaa\nbbb\n", 4), vec!["aaa\n", "bbb\n"]);
+    }
+
+    #[test]
+    fn counts_non_ascii_by_utf16() {
+        // 4 characters but 12 bytes per line
+        let text = "玩家名字\n玩家名字\n";
+        assert_eq!(split(text, 10), vec![text]);
+        assert_eq!(split(text, 9), vec!["玩家名字\n", "玩家名字\n"]);
+    }
+
+    #[test]
+    fn hard_wraps_long_lines() {
+        assert_eq!(
+            split("ab\ncdefghij\nk\n", 4),
+            vec!["ab\n", "cdef", "ghij", "k\n"]
+        );
+        assert_eq!(split("abcdefg\n", 4), vec!["abcd", "efg\n"]);
+        assert!(split("\n \n", 4).is_empty());
+    }
+
+    #[test]
+    fn never_splits_surrogate_pairs() {
+        let chunks = split("🙂🙂🙂", 3);
+        assert_eq!(chunks, vec!["🙂", "🙂", "🙂"]);
+        assert!(chunks.iter().all(|chunk| utf16_len(chunk) <= 3));
+    }
+
+    #[test]
+    fn respects_limit() {
+        let text = "ä".repeat(5000) + "\n" + &"line\n".repeat(2000);
+        let chunks = split(&text, MESSAGE_LIMIT);
+        assert!(chunks.iter().all(|chunk| utf16_len(chunk) <= MESSAGE_LIMIT));
+        assert_eq!(chunks.concat(), text);
+    }
+}
@@ -0,0 +1,178 @@
+use regex::Regex;
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+// Folds the copies of a line that follow it into a single line with a `(repeated ×N)` suffix,
+// where N counts the first copy too. The first copy is sent as it comes, the rest are held for at
+// most the window
+pub struct Collapser {
+    window: Duration,
+    // Matches the numbers, so lines differing only in them count as copies
+    numbers: Option<Regex>,
+    run: Option<Run>,
+}
+
+struct Run {
+    key: String,
+    // The latest copy, which is the one sent with the count
+    last: String,
+    // The copies held since the line was last sent
+    count: usize,
+    // The copies seen since the run started, including the first one
+    total: usize,
+    held_at: Instant,
+}
+
+impl Collapser {
+    // A zero window disables collapsing
+    pub fn new(window: Duration, ignore_numbers: bool) -> Self {
+        Self {
+            window,
+            numbers: ignore_numbers.then(|| Regex::new(r"\d+").unwrap()),
+            run: None,
+        }
+    }
+
+    // The lines to send for this one, in order
+    pub fn push(&mut self, text: &str, now: Instant) -> Vec<String> {
+        if self.window.is_zero() {
+            return vec![text.to_owned()];
+        }
+
+        let key = self.key(text);
+
+        if let Some(run) = &mut self.run {
+            if run.key == key {
+                if run.count == 0 {
+                    run.held_at = now;
+                }
+
+                run.count += 1;
+                run.total += 1;
+                run.last = text.to_owned();
+
+                // A storm longer than the window is reported once per window
+                return self.flush(now).into_iter().collect();
+            }
+        }
+
+        let mut lines = self.end().into_iter().collect::<Vec<_>>();
+        lines.push(text.to_owned());
+
+        self.run = Some(Run {
+            key,
+            last: text.to_owned(),
+            count: 0,
+            total: 1,
+            held_at: now,
+        });
+
+        lines
+    }
+
+    // When the held copies are due to be sent
+    pub fn deadline(&self) -> Option<Instant> {
+        self.run
+            .as_ref()
+            .filter(|run| run.count > 0)
+            .map(|run| run.held_at + self.window)
+    }
+
+    // Sends the held copies if they were held for the window, still collapsing the copies to come
+    pub fn flush(&mut self, now: Instant) -> Option<String> {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => self.take(),
+            _ => None,
+        }
+    }
+
+    // Sends the held copies, e.g. before a line that is not collapsed
+    pub fn end(&mut self) -> Option<String> {
+        let line = self.take();
+        self.run = None;
+        line
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let run = self.run.as_mut()?;
+        let count = mem::take(&mut run.count);
+
+        match count {
+            0 => None,
+            1 => Some(run.last.clone()),
+            _ => Some(format!(
+                "{} (repeated ×{})\n",
+                run.last.trim_end_matches('\n'),
+                run.total
+            )),
+        }
+    }
+
+    fn key(&self, text: &str) -> String {
+        let text = text.trim_end();
+
+        match &self.numbers {
+            Some(numbers) => numbers.replace_all(text, "#").into_owned(),
+            None => text.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_consecutive_copies() {
+        let mut collapser = Collapser::new(Duration::from_secs(10), false);
+        let now = Instant::now();
+
+        assert_eq!(collapser.push("Lag spike\n", now), ["Lag spike\n"]);
+        assert!(collapser.push("Lag spike\n", now).is_empty());
+        assert!(collapser.push("Lag spike\n", now).is_empty());
+        assert_eq!(
+            collapser.push("Map loaded.\n", now),
+            ["Lag spike (repeated ×3)\n", "Map loaded.\n"]
+        );
+
+        assert!(collapser.push("Map loaded.\n", now).is_empty());
+        assert_eq!(collapser.end().as_deref(), Some("Map loaded.\n"));
+        assert_eq!(collapser.end(), None);
+    }
+
+    #[test]
+    fn reports_once_per_window() {
+        let mut collapser = Collapser::new(Duration::from_secs(10), true);
+        let now = Instant::now();
+        let at = |seconds| now + Duration::from_secs(seconds);
+
+        collapser.push("Packet 1 dropped\n", at(0));
+        assert!(collapser.push("Packet 2 dropped\n", at(1)).is_empty());
+        assert_eq!(collapser.deadline(), Some(at(11)));
+        assert_eq!(collapser.flush(at(5)), None);
+        assert_eq!(
+            collapser.push("Packet 3 dropped\n", at(11)),
+            ["Packet 3 dropped (repeated ×3)\n"]
+        );
+
+        // The copies after the report are still collapsed
+        assert!(collapser.push("Packet 4 dropped\n", at(12)).is_empty());
+        assert!(collapser.push("Packet 5 dropped\n", at(13)).is_empty());
+        assert_eq!(
+            collapser.flush(at(22)).as_deref(),
+            Some("Packet 5 dropped (repeated ×5)\n")
+        );
+    }
+
+    #[test]
+    fn can_be_disabled() {
+        let mut collapser = Collapser::new(Duration::ZERO, false);
+        let now = Instant::now();
+
+        assert_eq!(collapser.push("Lag spike\n", now), ["Lag spike\n"]);
+        assert_eq!(collapser.push("Lag spike\n", now), ["Lag spike\n"]);
+        assert_eq!(collapser.deadline(), None);
+    }
+}
@@ -1,21 +1,66 @@
-use crate::bot::BotUpdateHandler;
+use crate::{
+    bot::BotUpdateHandler,
+    callback::{self, Confirmation},
+    chunk, duration,
+    format::OutputFormat,
+    history,
+    link::{self, PendingLink},
+    logfile, parse, playlist,
+    rules::Rule,
+    scrollback::{self, Line},
+    store::{self, JoinRequest, PlaylistEntry, Store},
+    tempban,
+    trigger::Trigger,
+    vote::{self, MapVote},
+};
 use futures_util::{future::BoxFuture, StreamExt};
 use itertools::Itertools;
-use std::{collections::HashMap, path::PathBuf, process, sync::Arc};
+use regex::Regex;
+use std::{collections::HashMap, io::Cursor, path::PathBuf, sync::Arc, time::Duration};
 use tgbot::{
-    methods::{GetFile, SendMessage},
-    types::{Command, MessageData},
+    methods::{DeleteMessage, EditMessageText, GetFile, SendDocument, SendMessage, SendPoll},
+    types::{
+        Command, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputFileInfo,
+        InputFileReader, Integer, MessageData, Poll,
+    },
     ExecuteError,
 };
-use tokio::{fs::File, io::AsyncWriteExt};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    time::{self, Instant},
+};
 
 type GameCommandHandler = Box<
     dyn Fn(BotUpdateHandler, Command) -> BoxFuture<'static, Result<(), ExecuteError>> + Send + Sync,
 >;
 
+const TOP_DEFAULT: usize = 10;
+const TOP_MAX: usize = 50;
+
+const HISTORY_DEFAULT: usize = 10;
+const HISTORY_MAX: usize = 30;
+
+const VOTE_DEFAULT: usize = 5;
+
+const TAIL_DEFAULT: usize = 20;
+const GREP_DEFAULT: usize = 50;
+const SCROLLBACK_MAX: usize = 100_000;
+
+const AUDIT_DEFAULT: usize = 20;
+const AUDIT_MAX: usize = 100;
+
+// The least time between two `/join` requests of a user, in seconds
+const JOIN_COOLDOWN: i64 = 10 * 60;
+
+// How long the map list of `/host` can be chosen from
+const MAP_LIST_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 pub struct GameCommand {
     pub description: String,
     pub handler: GameCommandHandler,
+    // Whether users other than the admin can use the command
+    pub is_public: bool,
 }
 
 pub struct GameCommandMap;
@@ -44,10 +89,28 @@ Useful Commands:
 
 /output - Send the output to current Telegram chat
 /stopoutput - Stop sending the output to current Telegram chat
+/format <plain/monospace> - Set the output format of current Telegram chat
 /help - Print the help menu
-/host [mapname] [mode] - Open the server. Will default to survival and a random map if not specified
+/host [mapname] [mode] - Open the server. Pick the map and the mode from a list if not specified
 /pause <on/off> - Pause or unpause the game
 /stop - Stop hosting the server
+/tempban <name/UUID> <duration> [reason] - Ban a player for a while, e.g. /tempban Anuke 1d12h griefing
+/tempbans - List the active temporary bans
+/join <UUID/name> - Ask to be added to the whitelist. Anyone can use it
+/link - Link your Telegram account to your player. Anyone can use it, as well as /whoami and /unlink
+/playtime [name/UUID] - Show the playtime of a player, or your own if linked
+/top [count] - Show the players with the most playtime
+/history [count/csv] - Show the recent matches, or export all of them as CSV
+/votemap [number of maps] - Start a poll to choose the next map
+/playlist [add/remove/move/shuffle/use/clear/next] - Manage the maps hosted one after another
+/players - List the players with buttons to kick, ban or promote them
+/tail [count] - Show the last lines of the output
+/grep <regex> [count] - Search the recent output
+/since <duration> - Show the output of the last while
+/logs [file] - List the log files, or send one
+/rules [add/remove] - Drop, forward, highlight or alert on the lines of output matching a regex
+/triggers [add/remove] - Run a command when a line of output matches a regex
+/audit [count] - Show the recent runs of triggers and changes to the rules
 /maps - Display all available maps
 /uploadmap - Upload a map to config/maps/
 /reloadmaps - Reload all maps from disk
@@ -63,6 +126,7 @@ Useful Commands:
             GameCommand {
                 description: String::new(),
                 handler: Box::new(about) as GameCommandHandler,
+                is_public: false,
             },
         );
         commands.insert(
@@ -70,6 +134,7 @@ Useful Commands:
             GameCommand {
                 description: String::from("About this bot"),
                 handler: Box::new(about) as GameCommandHandler,
+                is_public: false,
             },
         );
 
@@ -111,6 +176,7 @@ Useful Commands:
             GameCommand {
                 description: String::from("Print the help menu"),
                 handler: Box::new(help) as GameCommandHandler,
+                is_public: false,
             },
         );
 
@@ -123,14 +189,11 @@ Useful Commands:
 
                 let mut output_chat = handler.context.output_chat.lock().await;
 
-                let send_message;
-
-                if output_chat.insert(chat_id) {
-                    send_message = SendMessage::new(chat_id, "OKay, I will send the output here");
+                let send_message = if output_chat.insert(chat_id) {
+                    SendMessage::new(chat_id, "OKay, I will send the output here")
                 } else {
-                    send_message =
-                        SendMessage::new(chat_id, "This chat is already in the output chat list");
-                }
+                    SendMessage::new(chat_id, "This chat is already in the output chat list")
+                };
 
                 handler.api.execute(send_message).await?;
 
@@ -142,6 +205,7 @@ Useful Commands:
             GameCommand {
                 description: String::from("Send the output to this chat"),
                 handler: Box::new(output) as GameCommandHandler,
+                is_public: false,
             },
         );
 
@@ -154,15 +218,11 @@ Useful Commands:
 
                 let mut output_chat = handler.context.output_chat.lock().await;
 
-                let send_message;
-
-                if output_chat.remove(&chat_id) {
-                    send_message =
-                        SendMessage::new(chat_id, "Okay, I will not send the output here anymore");
+                let send_message = if output_chat.remove(&chat_id) {
+                    SendMessage::new(chat_id, "Okay, I will not send the output here anymore")
                 } else {
-                    send_message =
-                        SendMessage::new(chat_id, "This chat is not in the output chat list");
-                }
+                    SendMessage::new(chat_id, "This chat is not in the output chat list")
+                };
 
                 handler.api.execute(send_message).await?;
 
@@ -174,114 +234,1742 @@ Useful Commands:
             GameCommand {
                 description: String::from("Stop sending the output to this chat"),
                 handler: Box::new(stopoutput) as GameCommandHandler,
+                is_public: false,
             },
         );
 
-        fn uploadmap(
+        fn format(
             handler: BotUpdateHandler,
             command: Command,
         ) -> BoxFuture<'static, Result<(), ExecuteError>> {
             Box::pin(async move {
                 let chat_id = command.get_message().get_chat_id();
 
-                if let MessageData::Document { data, .. } = &command.get_message().data {
-                    let file_id = data.file_id.clone();
-                    let file_name = data.file_name.clone().unwrap_or(file_id.clone());
-
-                    let get_file = GetFile::new(file_id);
-                    let file = handler.api.execute(get_file).await?;
-
-                    let mut is_map_saved = false;
-
-                    if let Some(file_url) = file.file_path {
-                        if let Ok(mut file_stream) = handler.api.download_file(file_url).await {
-                            let file_path = {
-                                let mut path = PathBuf::from(r"config/maps/");
-                                path.push(file_name);
-                                path
-                            };
+                let mut output_format = handler.context.output_format.lock().await;
 
-                            if let Ok(mut map) = File::create(file_path).await {
-                                if let Some(file) = file_stream.next().await {
-                                    if let Ok(file) = file {
-                                        if let Ok(_) = map.write_all(&file).await {
-                                            is_map_saved = true;
-
-                                            let send_message = SendMessage::new(
-                                                chat_id,
-                                                "Map saved. Use /reloadmaps to reload all maps from disk",
-                                            );
-                                            handler.api.execute(send_message).await?;
-                                        }
-                                    }
+                let send_message = match command.get_args().first() {
+                    Some(arg) => match arg.parse::<OutputFormat>() {
+                        Ok(format) => {
+                            match handler
+                                .context
+                                .store
+                                .set_chat_format(chat_id, &format.to_string())
+                            {
+                                Ok(_) => {
+                                    output_format.insert(chat_id, format);
+                                    SendMessage::new(
+                                        chat_id,
+                                        format!(
+                                            "Okay, the output here will be sent as {} text",
+                                            format
+                                        ),
+                                    )
                                 }
+                                Err(e) => SendMessage::new(
+                                    chat_id,
+                                    format!("Failed to save the output format: {}", e),
+                                ),
                             }
                         }
+                        Err(e) => SendMessage::new(chat_id, e),
+                    },
+                    None => {
+                        let format = output_format
+                            .get(&chat_id)
+                            .copied()
+                            .unwrap_or(handler.context.default_format);
+                        SendMessage::new(
+                            chat_id,
+                            format!(
+                                "The output here is sent as {} text. Use /format <plain/monospace> to change it",
+                                format
+                            ),
+                        )
                     }
+                };
 
-                    if !is_map_saved {
-                        let send_message = SendMessage::new(chat_id, "Failed to save the map");
+                handler.api.execute(send_message).await?;
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/format"),
+            GameCommand {
+                description: String::from("<plain/monospace> Set the output format of this chat"),
+                handler: Box::new(format) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn tempban(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let args = command.get_args();
+
+                let (player, duration) = match (
+                    args.first(),
+                    args.get(1).and_then(|arg| duration::parse(arg)),
+                ) {
+                    (Some(player), Some(duration)) => (player, duration),
+                    _ => {
+                        let send_message = SendMessage::new(
+                            chat_id,
+                            "Usage: /tempban <name/UUID> <duration, e.g. 30m, 2h, 1d12h> [reason]",
+                        );
                         handler.api.execute(send_message).await?;
+                        return Ok(());
                     }
-                } else {
+                };
+                if duration > tempban::MAX_DURATION {
                     let send_message = SendMessage::new(
                         chat_id,
-                        "Please send the map file as an attachment of the command",
+                        format!(
+                            "A temporary ban lasts at most {}. Use /ban instead",
+                            duration::format(tempban::MAX_DURATION)
+                        ),
                     );
                     handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                let reason = args[2..].join(" ");
+
+                // The player is looked up among the players in game, or taken as a UUID
+                let players = match handler.query(String::from("players")).await {
+                    Some(output) => parse::players(&output),
+                    None => Vec::new(),
+                };
+                let (name, uuid) = match players.into_iter().find(|online| {
+                    online.name.eq_ignore_ascii_case(player) || &online.uuid == player
+                }) {
+                    Some(online) => (online.name, online.uuid),
+                    None if parse::is_uuid(player) => (player.clone(), player.clone()),
+                    None => {
+                        let send_message = SendMessage::new(
+                            chat_id,
+                            format!("Player {} is not in game. Use their UUID instead", player),
+                        );
+                        handler.api.execute(send_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                let user = match command.get_message().get_user() {
+                    Some(user) => user,
+                    None => return Ok(()),
+                };
+                let request = tempban::Request {
+                    name,
+                    uuid,
+                    reason,
+                    duration,
+                    banned_by: user.username.clone().unwrap_or_default(),
+                };
+
+                // A temporary ban is still a ban, so it is confirmed like one
+                if handler.context.confirm_commands.contains("ban") {
+                    let confirmation = Confirmation {
+                        command: format!("ban id {}", request.uuid),
+                        user_id: user.id,
+                        tempban: Some(request),
+                        player_list: None,
+                    };
+                    return handler.request_confirmation(chat_id, confirmation).await;
                 }
 
+                let text = tempban::apply(&handler, request).await;
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
                 Ok(())
             })
         }
         commands.insert(
-            String::from("/uploadmap"),
+            String::from("/tempban"),
             GameCommand {
-                description: String::from("Upload a map to config/maps/"),
-                handler: Box::new(uploadmap) as GameCommandHandler,
+                description: String::from(
+                    "<name/UUID> <duration> [reason] Ban a player for a while",
+                ),
+                handler: Box::new(tempban) as GameCommandHandler,
+                is_public: false,
             },
         );
 
-        fn generic_handler(
+        fn tempbans(
             handler: BotUpdateHandler,
             command: Command,
         ) -> BoxFuture<'static, Result<(), ExecuteError>> {
             Box::pin(async move {
-                let name = &command.get_name()[1..].replace('_', "-");
-                let args = command
-                    .get_args()
-                    .into_iter()
-                    .map(|arg| format!(" {}", arg))
-                    .collect::<String>();
+                let chat_id = command.get_message().get_chat_id();
+
+                let text = match handler.context.store.tempbans() {
+                    Ok(tempbans) if tempbans.is_empty() => String::from("No active temporary bans"),
+                    Ok(tempbans) => {
+                        let now = store::now();
+
+                        tempbans
+                            .iter()
+                            .map(|ban| {
+                                let remaining =
+                                    Duration::from_secs((ban.expires_at - now).max(0) as u64);
+                                let mut line = format!(
+                                    "{} ({}) - {} left",
+                                    ban.name,
+                                    ban.uuid,
+                                    duration::format(remaining)
+                                );
+
+                                if !ban.reason.is_empty() {
+                                    line.push_str(&format!(": {}", ban.reason));
+                                }
+
+                                line
+                            })
+                            .join("\n")
+                    }
+                    Err(e) => format!("Failed to read the temporary bans: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/tempbans"),
+            GameCommand {
+                description: String::from("List the active temporary bans"),
+                handler: Box::new(tempbans) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        // Why a request to join as the player is refused, if it is. The player is a name or UUID
+        fn join_refusal(store: &Store, user_id: Integer, player: &str) -> Option<String> {
+            match (
+                store.last_join_request(user_id),
+                store.has_pending_join_request(player),
+            ) {
+                (Ok(Some(last)), _) if store::now() - last < JOIN_COOLDOWN => Some(format!(
+                    "You can send another request in {}",
+                    duration::format(Duration::from_secs(
+                        (last + JOIN_COOLDOWN - store::now()) as u64
+                    ))
+                )),
+                (_, Ok(true)) => Some(format!(
+                    "A request to join as {} is already waiting for a decision",
+                    player
+                )),
+                (Err(e), _) | (_, Err(e)) => {
+                    log!("Failed to read the join requests: {}", e);
+                    Some(String::from("Failed to send the request, try again later"))
+                }
+                _ => None,
+            }
+        }
+
+        fn join(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let player = command.get_args().join(" ");
+
+                let user = match command.get_message().get_user() {
+                    Some(user) => user,
+                    None => return Ok(()),
+                };
+
+                if player.is_empty() {
+                    let send_message = SendMessage::new(chat_id, "Usage: /join <UUID/name>");
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                // Checked before the player is looked up, so repeated requests do not reach the
+                // console
+                if let Some(refusal) = join_refusal(&handler.context.store, user.id, &player) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, refusal))
+                        .await?;
+                    return Ok(());
+                }
+
+                // A user who is not whitelisted may not be able to stay in game, so the player is
+                // also looked up among the players seen before, or taken as a UUID
+                let players = match handler.query(String::from("players")).await {
+                    Some(output) => parse::players(&output),
+                    None => Vec::new(),
+                };
+                let seen = match handler.context.store.player_stats(&player) {
+                    Ok(seen) => seen,
+                    Err(e) => {
+                        log!("Failed to look up the player {}: {}", player, e);
+                        None
+                    }
+                };
+                let (name, uuid) = match players.into_iter().find(|online| {
+                    online.name.eq_ignore_ascii_case(&player) || online.uuid == player
+                }) {
+                    Some(online) => (online.name, online.uuid),
+                    None if parse::is_uuid(&player) => (player.clone(), player),
+                    None => match seen {
+                        Some(seen) => (seen.name, seen.uuid),
+                        None => {
+                            let send_message = SendMessage::new(
+                                chat_id,
+                                format!(
+                                    "Player {} has not been seen on the server. Send your UUID instead",
+                                    player
+                                ),
+                            );
+                            handler.api.execute(send_message).await?;
+                            return Ok(());
+                        }
+                    },
+                };
+
+                // The name may have been resolved to a UUID that already has a request
+                if let Some(refusal) = join_refusal(&handler.context.store, user.id, &uuid) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, refusal))
+                        .await?;
+                    return Ok(());
+                }
+
+                let username = match &user.username {
+                    Some(username) => format!("@{}", username),
+                    None => user.get_full_name(),
+                };
 
-                match handler.input_sender.send(format!("{}{}", name, args)).await {
-                    Ok(_) => Ok(()),
+                let mut request = JoinRequest {
+                    id: 0,
+                    uuid,
+                    name,
+                    user_id: user.id,
+                    username,
+                    chat_id,
+                };
+
+                request.id = match handler.context.store.add_join_request(&request) {
+                    Ok(id) => id,
                     Err(e) => {
-                        eprintln!(
-                            "Failed to communicate with the game proccess: {}",
-                            e.to_string()
+                        log!("Failed to save the join request: {}", e);
+                        let send_message = SendMessage::new(
+                            chat_id,
+                            "Failed to send the request, try again later",
                         );
-                        process::exit(1);
+                        handler.api.execute(send_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                // The request goes to the admin, or to the output chats until the admin has
+                // talked to the bot in private
+                let admin_chats = match *handler.context.admin_chat.lock().await {
+                    Some(admin_chat) => vec![admin_chat],
+                    None => handler
+                        .context
+                        .output_targets()
+                        .await
+                        .into_iter()
+                        .map(|(chat_id, _)| chat_id)
+                        .collect(),
+                };
+
+                let keyboard = vec![vec![
+                    InlineKeyboardButton::with_callback_data(
+                        "Approve",
+                        format!("join:approve:{}", request.id),
+                    ),
+                    InlineKeyboardButton::with_callback_data(
+                        "Deny",
+                        format!("join:deny:{}", request.id),
+                    ),
+                ]];
+
+                let mut is_sent = false;
+
+                for admin_chat in admin_chats {
+                    let send_message = SendMessage::new(
+                        admin_chat,
+                        format!(
+                            "{} asks to join the server\nPlayer: {}\nUUID: {}",
+                            request.username, request.name, request.uuid
+                        ),
+                    )
+                    .reply_markup(InlineKeyboardMarkup::from(keyboard.clone()));
+
+                    match handler.api.execute(send_message).await {
+                        Ok(_) => is_sent = true,
+                        Err(e) => {
+                            log!("Failed to send the join request to {}: {}", admin_chat, e)
+                        }
+                    }
+                }
+
+                let text = if is_sent {
+                    "Your request has been sent to the admins"
+                } else {
+                    "No admin can be reached right now, try again later"
+                };
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/join"),
+            GameCommand {
+                description: String::from("<UUID/name> Ask to be added to the whitelist"),
+                handler: Box::new(join) as GameCommandHandler,
+                is_public: true,
+            },
+        );
+
+        fn link(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let user_id = match command.get_message().get_user() {
+                    Some(user) => user.id,
+                    None => return Ok(()),
+                };
+
+                let code = link::new_code();
+
+                handler.context.link_codes.lock().await.insert(
+                    code.clone(),
+                    PendingLink {
+                        user_id,
+                        chat_id,
+                        created_at: Instant::now(),
+                    },
+                );
+
+                let send_message = SendMessage::new(
+                    chat_id,
+                    format!(
+                        "Send {} in the game chat within {} minutes to link your Telegram account to your player",
+                        code,
+                        link::CODE_TIMEOUT.as_secs() / 60
+                    ),
+                );
+                handler.api.execute(send_message).await?;
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/link"),
+            GameCommand {
+                description: String::from("Link your Telegram account to your player"),
+                handler: Box::new(link) as GameCommandHandler,
+                is_public: true,
+            },
+        );
+
+        fn unlink(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let user_id = match command.get_message().get_user() {
+                    Some(user) => user.id,
+                    None => return Ok(()),
+                };
+
+                let text = match handler.context.store.remove_link(user_id) {
+                    Ok(true) => {
+                        String::from("Your Telegram account is not linked to any player anymore")
+                    }
+                    Ok(false) => String::from("Your Telegram account is not linked to any player"),
+                    Err(e) => format!("Failed to unlink your account: {}", e),
+                };
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/unlink"),
+            GameCommand {
+                description: String::from("Unlink your Telegram account from your player"),
+                handler: Box::new(unlink) as GameCommandHandler,
+                is_public: true,
+            },
+        );
+
+        // Runs `info` on the linked player only, so anyone can see their own stats
+        fn whoami(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let user_id = match command.get_message().get_user() {
+                    Some(user) => user.id,
+                    None => return Ok(()),
+                };
+
+                let text = match handler.context.store.link(user_id) {
+                    Ok(Some(link)) => {
+                        let mut text = format!("You are {} ({})", link.name, link.uuid);
+
+                        if let Some(info) = handler.query(format!("info {}", link.uuid)).await {
+                            text.push_str(&format!("\n\n{}", info.trim()));
+                        }
+
+                        text
+                    }
+                    Ok(None) => String::from(
+                        "Your Telegram account is not linked to any player. Use /link to link it",
+                    ),
+                    Err(e) => format!("Failed to read your linked player: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/whoami"),
+            GameCommand {
+                description: String::from("Show the player linked to your Telegram account"),
+                handler: Box::new(whoami) as GameCommandHandler,
+                is_public: true,
+            },
+        );
+
+        // Without a name, shows the stats of the player linked to the user
+        fn playtime(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let mut player = command.get_args().join(" ");
+
+                if player.is_empty() {
+                    let link = match command.get_message().get_user() {
+                        Some(user) => handler.context.store.link(user.id).ok().flatten(),
+                        None => None,
+                    };
+
+                    match link {
+                        Some(link) => player = link.uuid,
+                        None => {
+                            let send_message = SendMessage::new(
+                                chat_id,
+                                "Usage: /playtime <name/UUID>, or /link your account first",
+                            );
+                            handler.api.execute(send_message).await?;
+                            return Ok(());
+                        }
                     }
                 }
+
+                let text = match handler.context.store.player_stats(&player) {
+                    Ok(Some(stats)) => {
+                        let now = store::now();
+                        let ago = |time: i64| {
+                            duration::format(Duration::from_secs((now - time).max(0) as u64))
+                        };
+
+                        format!(
+                            "{} ({})\nPlaytime: {}\nSessions: {}\nFirst seen: {} ago\nLast seen: {} ago",
+                            stats.name,
+                            stats.uuid,
+                            duration::format(Duration::from_secs(stats.playtime.max(0) as u64)),
+                            stats.sessions,
+                            ago(stats.first_seen),
+                            ago(stats.last_seen)
+                        )
+                    }
+                    Ok(None) => format!("Player {} has never been seen", player),
+                    Err(e) => format!("Failed to read the stats: {}", e),
+                };
+
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
+                Ok(())
             })
         }
+        commands.insert(
+            String::from("/playtime"),
+            GameCommand {
+                description: String::from("[name/UUID] Show the playtime of a player"),
+                handler: Box::new(playtime) as GameCommandHandler,
+                is_public: true,
+            },
+        );
 
-        for command in help_output.split('\n') {
-            if let Some((name, description)) = command.trim_start().split_once(' ') {
-                commands
-                    .entry(format!("/{}", name.replace('-', "_")))
-                    .or_insert(GameCommand {
-                        description: description
-                            .trim_start_matches("- ")
-                            .trim_end_matches('.')
-                            .to_string(),
-                        handler: Box::new(generic_handler) as GameCommandHandler,
-                    });
-            }
+        fn top(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let limit = command
+                    .get_args()
+                    .first()
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(TOP_DEFAULT)
+                    .clamp(1, TOP_MAX);
+
+                let text = match handler.context.store.top_players(limit) {
+                    Ok(players) if players.is_empty() => {
+                        String::from("No player has been seen yet")
+                    }
+                    Ok(players) => players
+                        .iter()
+                        .enumerate()
+                        .map(|(rank, stats)| {
+                            format!(
+                                "{}. {} - {}",
+                                rank + 1,
+                                stats.name,
+                                duration::format(Duration::from_secs(stats.playtime.max(0) as u64))
+                            )
+                        })
+                        .join("\n"),
+                    Err(e) => format!("Failed to read the stats: {}", e),
+                };
+
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
+                Ok(())
+            })
         }
+        commands.insert(
+            String::from("/top"),
+            GameCommand {
+                description: String::from("[count] Show the players with the most playtime"),
+                handler: Box::new(top) as GameCommandHandler,
+                is_public: true,
+            },
+        );
 
-        commands.remove("/exit");
+        fn history(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let arg = command.get_args().first().cloned();
+
+                if arg.as_deref() == Some("csv") {
+                    match handler.context.store.matches(None) {
+                        Ok(matches) => {
+                            let document = InputFileReader::new(Cursor::new(
+                                history::csv(&matches).into_bytes(),
+                            ))
+                            .info(InputFileInfo::new("history.csv"));
+
+                            let send_document =
+                                SendDocument::new(chat_id, InputFile::reader(document));
+                            handler.api.execute(send_document).await?;
+                        }
+                        Err(e) => {
+                            let send_message = SendMessage::new(
+                                chat_id,
+                                format!("Failed to read the match history: {}", e),
+                            );
+                            handler.api.execute(send_message).await?;
+                        }
+                    }
+
+                    return Ok(());
+                }
+
+                let limit = arg
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(HISTORY_DEFAULT)
+                    .clamp(1, HISTORY_MAX);
+
+                let text = match handler.context.store.matches(Some(limit)) {
+                    Ok(matches) if matches.is_empty() => {
+                        String::from("No match has been recorded yet")
+                    }
+                    Ok(matches) => matches.iter().map(history::summary).join("\n\n"),
+                    Err(e) => format!("Failed to read the match history: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/history"),
+            GameCommand {
+                description: String::from(
+                    "[count/csv] Show the recent matches, or export all of them",
+                ),
+                handler: Box::new(history) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn playlist(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let args = command.get_args();
+
+                let text = match args.first().map(String::as_str) {
+                    Some("next") => {
+                        let is_hosted = handler.context.current_match.lock().await.is_some();
+
+                        match playlist::host_next(&handler, is_hosted).await {
+                            Ok(Some(entry)) => {
+                                format!("Hosting {} in {} mode", entry.map, entry.mode)
+                            }
+                            Ok(None) => String::from("The playlist is empty"),
+                            Err(e) => format!("Failed to host the next map: {}", e),
+                        }
+                    }
+                    _ => match edit_playlist(&handler.context.store, args) {
+                        Ok(text) => {
+                            // The playlist may have been emptied or switched for an empty one
+                            if let Err(e) = playlist::restore_rotation(&handler).await {
+                                log!(
+                                    "Failed to turn the map rotation of the server back on: {}",
+                                    e
+                                );
+                            }
+                            text
+                        }
+                        Err(e) => format!("Failed to update the playlist: {}", e),
+                    },
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/playlist"),
+            GameCommand {
+                description: String::from(
+                    "[add/remove/move/shuffle/use/clear/next] Manage the map playlist",
+                ),
+                handler: Box::new(playlist) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn edit_playlist(store: &Store, args: &[String]) -> Result<String, String> {
+            let name = playlist::active(store)?;
+            let mut entries = store.playlist(&name)?;
+
+            // Positions are shown and given starting from 1
+            let position = |arg: Option<&String>, len: usize| {
+                arg.and_then(|arg| arg.parse::<usize>().ok())
+                    .filter(|position| (1..=len).contains(position))
+                    .map(|position| position - 1)
+            };
+
+            let last = playlist::position(store)?;
+
+            let text = match args.first().map(String::as_str) {
+                None => {
+                    let order = if playlist::is_shuffled(store)? {
+                        "shuffled"
+                    } else {
+                        "in order"
+                    };
+
+                    let mut text = format!("Playlist {}, played {}", name, order);
+
+                    if entries.is_empty() {
+                        text.push_str("\nNo entries. Use /playlist add <map> [mode] to add one");
+                    }
+
+                    for (position, entry) in entries.iter().enumerate() {
+                        let marker = if Some(position) == last { " (last hosted)" } else { "" };
+                        text.push_str(&format!(
+                            "\n{}. {} ({}){}",
+                            position + 1,
+                            entry.map,
+                            entry.mode,
+                            marker
+                        ));
+                    }
+
+                    return Ok(text);
+                }
+                Some("add") => {
+                    let mut map = args[1..].to_vec();
+
+                    let mode = match map.last() {
+                        Some(mode) if map.len() > 1 && callback::GAME_MODES.contains(&mode.as_str()) => {
+                            map.pop().unwrap_or_default()
+                        }
+                        _ => String::from("survival"),
+                    };
+
+                    if map.is_empty() {
+                        return Ok(String::from("Usage: /playlist add <map> [mode]"));
+                    }
+
+                    let entry = PlaylistEntry {
+                        map: map.join(" "),
+                        mode,
+                    };
+                    let text = format!("Added {} ({})", entry.map, entry.mode);
+                    entries.push(entry);
+                    text
+                }
+                Some("remove") => match position(args.get(1), entries.len()) {
+                    Some(position) => {
+                        let entry = entries.remove(position);
+                        if let Some(last) = last {
+                            playlist::set_position(
+                                store,
+                                playlist::position_after_remove(last, position),
+                            )?;
+                        }
+                        format!("Removed {} ({})", entry.map, entry.mode)
+                    }
+                    None => return Ok(String::from("Usage: /playlist remove <position>")),
+                },
+                Some("move") => {
+                    match (
+                        position(args.get(1), entries.len()),
+                        position(args.get(2), entries.len()),
+                    ) {
+                        (Some(from), Some(to)) => {
+                            let entry = entries.remove(from);
+                            let text = format!("Moved {} to position {}", entry.map, to + 1);
+                            entries.insert(to, entry);
+                            if let Some(last) = last {
+                                playlist::set_position(
+                                    store,
+                                    Some(playlist::position_after_move(last, from, to)),
+                                )?;
+                            }
+                            text
+                        }
+                        _ => return Ok(String::from("Usage: /playlist move <from> <to>")),
+                    }
+                }
+                Some("shuffle") => {
+                    return match args.get(1).map(String::as_str) {
+                        Some("on") => {
+                            playlist::set_shuffled(store, true)?;
+                            Ok(String::from("The playlist will be played shuffled"))
+                        }
+                        Some("off") => {
+                            playlist::set_shuffled(store, false)?;
+                            Ok(String::from("The playlist will be played in order"))
+                        }
+                        _ => Ok(String::from("Usage: /playlist shuffle <on/off>")),
+                    };
+                }
+                Some("use") => {
+                    return match args.get(1) {
+                        Some(name) => {
+                            playlist::set_active(store, name)?;
+                            Ok(format!("Using playlist {}", name))
+                        }
+                        None => Ok(String::from("Usage: /playlist use <name>")),
+                    };
+                }
+                Some("clear") => {
+                    entries.clear();
+                    playlist::set_position(store, None)?;
+                    format!("Cleared playlist {}", name)
+                }
+                Some(_) => {
+                    return Ok(String::from(
+                        "Usage: /playlist [add <map> [mode]/remove <position>/move <from> <to>/shuffle <on/off>/use <name>/clear/next]",
+                    ))
+                }
+            };
+
+            store.set_playlist(&name, &entries)?;
+
+            Ok(text)
+        }
+
+        fn votemap(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                // Anyone can start a vote, so it is kept to the chats of the server
+                if !vote::is_vote_chat(&handler, chat_id).await {
+                    let send_message = SendMessage::new(
+                        chat_id,
+                        "Map votes can only be held in the bridge chat or a chat that gets the output",
+                    );
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                let count = match command.get_args().first() {
+                    Some(count) => match count.parse::<usize>() {
+                        Ok(count) if (vote::MIN_OPTIONS..=vote::MAX_OPTIONS).contains(&count) => {
+                            count
+                        }
+                        _ => {
+                            let send_message = SendMessage::new(
+                                chat_id,
+                                format!(
+                                    "Usage: /votemap [number of maps ({} ~ {})]",
+                                    vote::MIN_OPTIONS,
+                                    vote::MAX_OPTIONS
+                                ),
+                            );
+                            handler.api.execute(send_message).await?;
+                            return Ok(());
+                        }
+                    },
+                    None => VOTE_DEFAULT,
+                };
+
+                if handler.context.map_vote.lock().await.is_some() {
+                    let send_message =
+                        SendMessage::new(chat_id, "A map vote is already in progress");
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                let maps = match handler.query(String::from("maps")).await {
+                    Some(output) => vote::sample(&parse::maps(&output), count),
+                    None => Vec::new(),
+                };
+
+                if maps.len() < vote::MIN_OPTIONS {
+                    let send_message = SendMessage::new(chat_id, "Not enough maps to vote on");
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                let duration = handler.context.vote_duration;
+
+                let send_poll = maps
+                    .iter()
+                    .fold(
+                        SendPoll::new(chat_id, "Vote for the next map"),
+                        |send_poll, map| send_poll.option(map),
+                    )
+                    // Answers to anonymous polls are not sent to the bot
+                    .is_anonymous(false)
+                    .open_period(duration.as_secs() as Integer);
+                let message = handler.api.execute(send_poll).await?;
+
+                let poll_id = match message.data {
+                    MessageData::Poll(Poll::Regular(poll)) => poll.id,
+                    _ => return Ok(()),
+                };
+
+                let mut map_vote = handler.context.map_vote.lock().await;
+
+                // Another vote may have been started in the meantime
+                if map_vote.is_some() {
+                    handler
+                        .api
+                        .execute(DeleteMessage::new(chat_id, message.id))
+                        .await?;
+                    return Ok(());
+                }
+
+                *map_vote = Some(MapVote {
+                    poll_id: poll_id.clone(),
+                    chat_id,
+                    message_id: message.id,
+                    maps,
+                    ballots: HashMap::new(),
+                });
+
+                tokio::spawn(vote::stop_after(handler.clone(), poll_id, duration));
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/votemap"),
+            GameCommand {
+                description: String::from("[number of maps] Start a poll to choose the next map"),
+                handler: Box::new(votemap) as GameCommandHandler,
+                is_public: true,
+            },
+        );
+
+        fn tail(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let count = command
+                    .get_args()
+                    .first()
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(TAIL_DEFAULT)
+                    .clamp(1, SCROLLBACK_MAX);
+
+                let lines = handler.context.scrollback.tail(count).await;
+                send_lines(&handler, chat_id, lines, "tail.txt").await
+            })
+        }
+        commands.insert(
+            String::from("/tail"),
+            GameCommand {
+                description: String::from("[count] Show the last lines of the output"),
+                handler: Box::new(tail) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn grep(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let mut args = command.get_args().to_vec();
+
+                // The pattern may contain spaces, so the count is only taken from the end
+                let count = match args.last().map(|count| count.parse::<usize>()) {
+                    Some(Ok(count)) if args.len() > 1 => {
+                        args.pop();
+                        count.clamp(1, SCROLLBACK_MAX)
+                    }
+                    _ => GREP_DEFAULT,
+                };
+
+                if args.is_empty() {
+                    let send_message = SendMessage::new(chat_id, "Usage: /grep <regex> [count]");
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                let lines = match Regex::new(&args.join(" ")) {
+                    Ok(regex) => handler.context.scrollback.grep(regex, count).await,
+                    Err(e) => Err(format!("Invalid regex: {}", e)),
+                };
+
+                send_lines(&handler, chat_id, lines, "grep.txt").await
+            })
+        }
+        commands.insert(
+            String::from("/grep"),
+            GameCommand {
+                description: String::from("<regex> [count] Search the recent output"),
+                handler: Box::new(grep) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn since(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let duration = match command
+                    .get_args()
+                    .first()
+                    .and_then(|arg| duration::parse(arg))
+                {
+                    Some(duration) => duration,
+                    None => {
+                        let send_message =
+                            SendMessage::new(chat_id, "Usage: /since <duration>, e.g. /since 30m");
+                        handler.api.execute(send_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                let lines = handler
+                    .context
+                    .scrollback
+                    .since(store::now() - duration.as_secs() as i64)
+                    .await;
+                send_lines(&handler, chat_id, lines, "since.txt").await
+            })
+        }
+        commands.insert(
+            String::from("/since"),
+            GameCommand {
+                description: String::from("<duration> Show the output of the last while"),
+                handler: Box::new(since) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        // Sends the lines found in the scrollback as a message, or as a file if they do not fit
+        async fn send_lines(
+            handler: &BotUpdateHandler,
+            chat_id: i64,
+            lines: Result<Vec<Line>, String>,
+            name: &str,
+        ) -> Result<(), ExecuteError> {
+            let text = match lines {
+                Ok(lines) if lines.is_empty() => String::from("No lines found"),
+                Ok(lines) => scrollback::render(&lines),
+                Err(e) => format!("Failed to read the output: {}", e),
+            };
+
+            if chunk::utf16_len(&text) <= chunk::MESSAGE_LIMIT {
+                handler.api.execute(SendMessage::new(chat_id, text)).await?;
+            } else {
+                let document = InputFileReader::new(Cursor::new(text.into_bytes()))
+                    .info(InputFileInfo::new(name));
+
+                let send_document = SendDocument::new(chat_id, InputFile::reader(document));
+                handler.api.execute(send_document).await?;
+            }
+
+            Ok(())
+        }
+
+        fn logs(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                if let Some(name) = command.get_args().first() {
+                    return callback::send_log(&handler, chat_id, name).await;
+                }
+
+                let files = match logfile::list() {
+                    Ok(files) if files.is_empty() => {
+                        let send_message = SendMessage::new(chat_id, "No log files found");
+                        handler.api.execute(send_message).await?;
+                        return Ok(());
+                    }
+                    Ok(files) => files,
+                    Err(e) => {
+                        let send_message = SendMessage::new(
+                            chat_id,
+                            format!("Failed to list the log files: {}", e),
+                        );
+                        handler.api.execute(send_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                // Leave room for the cancel button, the newest files are listed first
+                let shown = files.len().min(callback::KEYBOARD_LIMIT - 1);
+
+                let mut keyboard = files
+                    .iter()
+                    .take(shown)
+                    .map(|file| {
+                        vec![InlineKeyboardButton::with_callback_data(
+                            format!("{} ({} KB)", file.name, file.size.div_ceil(1024)),
+                            format!("log_file:{}", file.name),
+                        )]
+                    })
+                    .collect::<Vec<_>>();
+                keyboard.push(vec![InlineKeyboardButton::with_callback_data(
+                    "Cancel", "cancel",
+                )]);
+
+                let text = if shown < files.len() {
+                    format!(
+                        "Choose a log file to download ({} of {} shown, use /logs <file> for the rest)",
+                        shown,
+                        files.len()
+                    )
+                } else {
+                    String::from("Choose a log file to download")
+                };
+
+                let send_message = SendMessage::new(chat_id, text)
+                    .reply_markup(InlineKeyboardMarkup::from(keyboard));
+                handler.api.execute(send_message).await?;
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/logs"),
+            GameCommand {
+                description: String::from("[file] List the log files, or send one"),
+                handler: Box::new(logs) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn rules(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let username = command
+                    .get_message()
+                    .get_user()
+                    .and_then(|user| user.username.clone())
+                    .unwrap_or_default();
+
+                let text = match edit_rules(&handler, command.get_args(), &username).await {
+                    Ok(text) => text,
+                    Err(e) => format!("Failed to update the output rules: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/rules"),
+            GameCommand {
+                description: String::from("[add/remove] Manage the rules applied to the output"),
+                handler: Box::new(rules) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        async fn edit_rules(
+            handler: &BotUpdateHandler,
+            args: &[String],
+            username: &str,
+        ) -> Result<String, String> {
+            let text = match args.first().map(String::as_str) {
+                None => {
+                    let rules = handler.context.rules.read().await;
+
+                    if rules.is_empty() {
+                        return Ok(String::from(
+                            "No output rules. Use /rules add <action> <regex> to add one",
+                        ));
+                    }
+
+                    let mut text = String::from("Output rules, the first match applies:");
+
+                    for (position, rule) in rules.iter().enumerate() {
+                        let marker = if rule.id.is_none() { " (command line)" } else { "" };
+                        text.push_str(&format!("\n{}. {}{}", position + 1, rule, marker));
+                    }
+
+                    return Ok(text);
+                }
+                Some("add") if args.len() > 2 => {
+                    // The regex may contain spaces
+                    let pattern = args[2..].join(" ");
+                    let rule = Rule::new(None, &args[1], &pattern)?;
+
+                    handler
+                        .context
+                        .store
+                        .add_rule(&rule.action.to_string(), &pattern, username)?;
+
+                    format!("Added the rule {}", rule)
+                }
+                Some("remove") => {
+                    // Positions are shown and given starting from 1
+                    let position = args
+                        .get(1)
+                        .and_then(|arg| arg.parse::<usize>().ok())
+                        .and_then(|position| position.checked_sub(1));
+
+                    // Cloned, as the rules are locked again to reload them
+                    let rule = match position {
+                        Some(position) => handler.context.rules.read().await.get(position).cloned(),
+                        None => None,
+                    };
+
+                    let rule = match rule {
+                        Some(rule) => rule,
+                        None => return Ok(String::from("Usage: /rules remove <position>")),
+                    };
+
+                    let id = match rule.id {
+                        Some(id) => id,
+                        None => {
+                            return Ok(format!(
+                                "The rule {} is set on the command line and cannot be removed",
+                                rule
+                            ))
+                        }
+                    };
+
+                    handler.context.store.remove_rule(id)?;
+
+                    format!("Removed the rule {}", rule)
+                }
+                Some(_) => {
+                    return Ok(String::from(
+                        "Usage: /rules [add <action> <regex>/remove <position>]\nActions: drop, forward:<chat id>[,<chat id>], highlight[:<emoji>], alert",
+                    ))
+                }
+            };
+
+            handler.context.reload_rules().await?;
+
+            if let Err(e) = handler.context.store.audit(username, &text) {
+                log!("Failed to write to the audit log: {}", e);
+            }
+
+            Ok(text)
+        }
+
+        fn triggers(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+                let username = command
+                    .get_message()
+                    .get_user()
+                    .and_then(|user| user.username.clone())
+                    .unwrap_or_default();
+
+                let text = match edit_triggers(&handler, command.get_args(), &username).await {
+                    Ok(text) => text,
+                    Err(e) => format!("Failed to update the triggers: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/triggers"),
+            GameCommand {
+                description: String::from(
+                    "[add/remove] Manage the commands run when the output matches a regex",
+                ),
+                handler: Box::new(triggers) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        async fn edit_triggers(
+            handler: &BotUpdateHandler,
+            args: &[String],
+            username: &str,
+        ) -> Result<String, String> {
+            let text = match args.first().map(String::as_str) {
+                None => {
+                    let triggers = handler.context.triggers.read().await;
+
+                    if triggers.is_empty() {
+                        return Ok(String::from(
+                            "No triggers. Use /triggers add <regex> => <command> to add one",
+                        ));
+                    }
+
+                    let mut text = String::from("Triggers:");
+
+                    for (position, trigger) in triggers.iter().enumerate() {
+                        let marker = if trigger.id.is_none() { " (command line)" } else { "" };
+                        text.push_str(&format!("\n{}. {}{}", position + 1, trigger, marker));
+                    }
+
+                    return Ok(text);
+                }
+                Some("add") if args.len() > 1 => {
+                    let trigger = args[1..].join(" ").parse::<Trigger>()?;
+
+                    handler.context.store.add_trigger(
+                        trigger.regex.as_str(),
+                        &trigger.command,
+                        username,
+                    )?;
+
+                    format!("Added the trigger {}", trigger)
+                }
+                Some("remove") => {
+                    // Positions are shown and given starting from 1
+                    let position = args
+                        .get(1)
+                        .and_then(|arg| arg.parse::<usize>().ok())
+                        .and_then(|position| position.checked_sub(1));
+
+                    // Cloned, as the triggers are locked again to reload them
+                    let trigger = match position {
+                        Some(position) => handler.context.triggers.read().await.get(position).cloned(),
+                        None => None,
+                    };
+
+                    let trigger = match trigger {
+                        Some(trigger) => trigger,
+                        None => return Ok(String::from("Usage: /triggers remove <position>")),
+                    };
+
+                    let id = match trigger.id {
+                        Some(id) => id,
+                        None => {
+                            return Ok(format!(
+                                "The trigger {} is set on the command line and cannot be removed",
+                                trigger
+                            ))
+                        }
+                    };
+
+                    handler.context.store.remove_trigger(id)?;
+
+                    format!("Removed the trigger {}", trigger)
+                }
+                Some(_) => {
+                    return Ok(String::from(
+                        "Usage: /triggers [add <regex> => <command>/remove <position>]\nThe command can use the groups captured by the regex as $1 or ${name}",
+                    ))
+                }
+            };
+
+            handler.context.reload_triggers().await?;
+
+            if let Err(e) = handler.context.store.audit(username, &text) {
+                log!("Failed to write to the audit log: {}", e);
+            }
+
+            Ok(text)
+        }
+
+        fn audit(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                let limit = command
+                    .get_args()
+                    .first()
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(AUDIT_DEFAULT)
+                    .clamp(1, AUDIT_MAX);
+
+                let now = store::now();
+
+                let text = match handler.context.store.audit_log(limit) {
+                    Ok(entries) if entries.is_empty() => String::from("The audit log is empty"),
+                    Ok(entries) => entries
+                        .iter()
+                        .map(|entry| {
+                            let ago = Duration::from_secs((now - entry.time).max(0) as u64);
+                            format!(
+                                "{} ago, {}: {}",
+                                duration::format(ago),
+                                entry.actor,
+                                entry.action
+                            )
+                        })
+                        .join("\n"),
+                    Err(e) => format!("Failed to read the audit log: {}", e),
+                };
+
+                for chunk in chunk::split(&text, chunk::MESSAGE_LIMIT) {
+                    handler
+                        .api
+                        .execute(SendMessage::new(chat_id, chunk))
+                        .await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/audit"),
+            GameCommand {
+                description: String::from(
+                    "[count] Show the recent runs of triggers and changes to the rules",
+                ),
+                handler: Box::new(audit) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn uploadmap(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let chat_id = command.get_message().get_chat_id();
+
+                if let MessageData::Document { data, .. } = &command.get_message().data {
+                    let file_id = data.file_id.clone();
+                    let file_name = data.file_name.clone().unwrap_or(file_id.clone());
+
+                    let get_file = GetFile::new(file_id);
+                    let file = handler.api.execute(get_file).await?;
+
+                    let mut is_map_saved = false;
+
+                    if let Some(file_url) = file.file_path {
+                        if let Ok(mut file_stream) = handler.api.download_file(file_url).await {
+                            let file_path = {
+                                let mut path = PathBuf::from(r"config/maps/");
+                                path.push(file_name);
+                                path
+                            };
+
+                            if let Ok(mut map) = File::create(file_path).await {
+                                if let Some(Ok(file)) = file_stream.next().await {
+                                    if map.write_all(&file).await.is_ok() {
+                                        is_map_saved = true;
+
+                                        let send_message = SendMessage::new(
+                                            chat_id,
+                                            "Map saved. Use /reloadmaps to reload all maps from disk",
+                                        );
+                                        handler.api.execute(send_message).await?;
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if !is_map_saved {
+                        let send_message = SendMessage::new(chat_id, "Failed to save the map");
+                        handler.api.execute(send_message).await?;
+                    }
+                } else {
+                    let send_message = SendMessage::new(
+                        chat_id,
+                        "Please send the map file as an attachment of the command",
+                    );
+                    handler.api.execute(send_message).await?;
+                }
+
+                Ok(())
+            })
+        }
+        commands.insert(
+            String::from("/uploadmap"),
+            GameCommand {
+                description: String::from("Upload a map to config/maps/"),
+                handler: Box::new(uploadmap) as GameCommandHandler,
+                is_public: false,
+            },
+        );
+
+        fn generic_handler(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                let name = &command.get_name()[1..].replace('_', "-");
+                let args = command
+                    .get_args()
+                    .iter()
+                    .map(|arg| format!(" {}", arg))
+                    .collect::<String>();
+
+                let input = format!("{}{}", name, args);
+
+                if handler.context.confirm_commands.contains(name) {
+                    let chat_id = command.get_message().get_chat_id();
+
+                    return match command.get_message().get_user() {
+                        Some(user) => {
+                            let confirmation = Confirmation {
+                                command: input,
+                                user_id: user.id,
+                                tempban: None,
+                                player_list: None,
+                            };
+                            handler.request_confirmation(chat_id, confirmation).await
+                        }
+                        None => Ok(()),
+                    };
+                }
+
+                handler.send_command(input).await;
+
+                Ok(())
+            })
+        }
+
+        for command in help_output.split('\n') {
+            if let Some((name, description)) = command.trim_start().split_once(' ') {
+                commands
+                    .entry(format!("/{}", name.replace('-', "_")))
+                    .or_insert(GameCommand {
+                        description: description
+                            .trim_start_matches("- ")
+                            .trim_end_matches('.')
+                            .to_string(),
+                        handler: Box::new(generic_handler) as GameCommandHandler,
+                        is_public: false,
+                    });
+            }
+        }
+
+        commands.remove("/exit");
+
+        // Without arguments, `/host` lets the user pick the map and the mode from a keyboard
+        fn host(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                if !command.get_args().is_empty() {
+                    return generic_handler(handler, command).await;
+                }
+
+                let chat_id = command.get_message().get_chat_id();
+
+                let maps = match handler.query(String::from("maps")).await {
+                    Some(output) => parse::maps(&output),
+                    None => Vec::new(),
+                };
+
+                if maps.is_empty() {
+                    let send_message = SendMessage::new(chat_id, "No maps found");
+                    handler.api.execute(send_message).await?;
+                    return Ok(());
+                }
+
+                // Leave room for the cancel button
+                let shown = maps.len().min(callback::KEYBOARD_LIMIT - 1);
+
+                let mut keyboard = maps
+                    .iter()
+                    .take(shown)
+                    .enumerate()
+                    .map(|(index, map)| {
+                        InlineKeyboardButton::with_callback_data(map, format!("host_map:{}", index))
+                    })
+                    .chunks(2)
+                    .into_iter()
+                    .map(|row| row.collect())
+                    .collect::<Vec<_>>();
+                keyboard.push(vec![InlineKeyboardButton::with_callback_data(
+                    "Cancel", "cancel",
+                )]);
+
+                let text = if shown < maps.len() {
+                    format!(
+                        "Choose a map ({} of {} shown, use /host <map> for the rest)",
+                        shown,
+                        maps.len()
+                    )
+                } else {
+                    String::from("Choose a map")
+                };
+
+                let send_message = SendMessage::new(chat_id, text)
+                    .reply_markup(InlineKeyboardMarkup::from(keyboard));
+                let message = handler.api.execute(send_message).await?;
+
+                handler
+                    .context
+                    .maps
+                    .lock()
+                    .await
+                    .insert((chat_id, message.id), maps);
+
+                // A list nobody chooses from is removed, so the lists do not pile up
+                tokio::spawn(async move {
+                    time::sleep(MAP_LIST_TIMEOUT).await;
+
+                    let maps = handler
+                        .context
+                        .maps
+                        .lock()
+                        .await
+                        .remove(&(chat_id, message.id));
+
+                    if maps.is_some() {
+                        let edit_message_text =
+                            EditMessageText::new(chat_id, message.id, "This map list has expired");
+
+                        if let Err(e) = handler.api.execute(edit_message_text).await {
+                            log!("{}", e);
+                        }
+                    }
+                });
+
+                Ok(())
+            })
+        }
+        if let Some(game_command) = commands.get_mut("/host") {
+            game_command.handler = Box::new(host) as GameCommandHandler;
+        }
+
+        // Without arguments, `/players` lists the players with buttons to moderate them
+        fn players(
+            handler: BotUpdateHandler,
+            command: Command,
+        ) -> BoxFuture<'static, Result<(), ExecuteError>> {
+            Box::pin(async move {
+                if !command.get_args().is_empty() {
+                    return generic_handler(handler, command).await;
+                }
+
+                let chat_id = command.get_message().get_chat_id();
+
+                let (text, keyboard) = callback::player_list(&handler, None).await;
+
+                let mut send_message = SendMessage::new(chat_id, text);
+
+                if let Some(keyboard) = keyboard {
+                    send_message = send_message.reply_markup(keyboard);
+                }
+
+                handler.api.execute(send_message).await?;
+
+                Ok(())
+            })
+        }
+        if let Some(game_command) = commands.get_mut("/players") {
+            game_command.handler = Box::new(players) as GameCommandHandler;
+        }
 
         commands
     }
@@ -1,10 +1,43 @@
-use getopts::Options;
+use crate::{
+    format::OutputFormat,
+    idle::IdlePolicy,
+    rules::Rule,
+    trigger::Trigger,
+    watchdog::{ThreadDump, Watchdog},
+};
+use getopts::{Matches, Options};
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
 pub struct Config {
     pub token: String,
     pub user: String,
     pub proxy: Option<String>,
     pub webhook: u16,
+    pub format: OutputFormat,
+    pub file_threshold: usize,
+    pub collapse_window: Duration,
+    pub collapse_numbers: bool,
+    pub confirm_commands: HashSet<String>,
+    pub confirm_timeout: Duration,
+    pub self_commands: HashSet<String>,
+    pub status_chats: Vec<i64>,
+    pub status_interval: Duration,
+    pub bridge_chat: Option<i64>,
+    pub data: String,
+    pub idle_policy: IdlePolicy,
+    pub idle_timeout: Duration,
+    pub vote_duration: Duration,
+    pub watchdog: Option<Watchdog>,
+    pub alert_chats: Vec<i64>,
+    pub crash_lines: usize,
+    pub scrollback: usize,
+    pub log_dir: String,
+    pub log_size: u64,
+    pub log_retention: usize,
+    pub rules: Vec<Rule>,
+    pub triggers: Vec<Trigger>,
+    pub trigger_cooldown: Duration,
+    pub trigger_limit: usize,
     pub file: String,
 }
 
@@ -36,13 +69,178 @@ impl Config {
             "set webhook port (1 ~ 65535) and run bot in webhook mode",
             "WEBHOOK_PORT",
         );
+        opts.optopt(
+            "f",
+            "format",
+            "set the default output format (plain, monospace)",
+            "FORMAT",
+        );
+        opts.optopt(
+            "",
+            "file-threshold",
+            "send outputs longer than this many characters as a text file (default: 16384, 0 to disable)",
+            "CHARS",
+        );
+        opts.optopt(
+            "",
+            "confirm",
+            "set the comma-separated commands that need confirmation (default: stop,gameover,ban,load)",
+            "COMMANDS",
+        );
+        opts.optopt(
+            "",
+            "confirm-timeout",
+            "set the seconds a confirmation stays valid (default: 60)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "self-commands",
+            "set the comma-separated commands linked users may run on their own player only, e.g. kick (default: none)",
+            "COMMANDS",
+        );
+        opts.optmulti(
+            "",
+            "status-chat",
+            "keep a status message pinned in this chat (can be repeated)",
+            "CHAT_ID",
+        );
+        opts.optopt(
+            "",
+            "status-interval",
+            "set the seconds between status message refreshes (default: 60)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "bridge-chat",
+            "relay messages between this Telegram group and the in-game chat",
+            "CHAT_ID",
+        );
+        opts.optopt(
+            "d",
+            "data",
+            "set the database file teledustry keeps its data in (default: teledustry.db)",
+            "PATH",
+        );
+        opts.optopt(
+            "",
+            "idle-policy",
+            "set what to do when no player is online (none, pause, stop) (default: none)",
+            "POLICY",
+        );
+        opts.optopt(
+            "",
+            "idle-minutes",
+            "set the minutes without players before the idle policy applies (default: 5)",
+            "MINUTES",
+        );
+        opts.optopt(
+            "",
+            "vote-duration",
+            "set the seconds a /votemap poll stays open (5 ~ 600) (default: 60)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "watchdog-interval",
+            "send a probe to the game server this often to detect hangs (default: 0, disabled)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "watchdog-timeout",
+            "set the seconds the game server has to respond to a probe (default: 10)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "watchdog-misses",
+            "restart the game server after this many missed probes in a row (default: 3)",
+            "COUNT",
+        );
+        opts.optopt(
+            "",
+            "thread-dump",
+            "capture the threads of a hung game server before restarting it (none, jstack, sigquit) (default: none)",
+            "METHOD",
+        );
+        opts.optmulti(
+            "",
+            "alert-chat",
+            "send alerts about the game server to this chat instead of the admin and output chats (can be repeated)",
+            "CHAT_ID",
+        );
+        opts.optopt(
+            "",
+            "crash-lines",
+            "set the lines of output included in a crash report (default: 200)",
+            "LINES",
+        );
+        opts.optopt(
+            "",
+            "scrollback",
+            "set the lines of output kept in memory for /tail, /grep and /since, which read the older ones from the log files (default: 1000)",
+            "LINES",
+        );
+        opts.optopt(
+            "",
+            "log-dir",
+            "set the directory the output and diagnostics are logged to (default: logs)",
+            "PATH",
+        );
+        opts.optopt(
+            "",
+            "log-size",
+            "rotate the log file once it reaches this many megabytes, or a new day begins (default: 10)",
+            "MEGABYTES",
+        );
+        opts.optopt(
+            "",
+            "log-retention",
+            "set the number of rotated log files kept (default: 10)",
+            "COUNT",
+        );
+        opts.optmulti(
+            "",
+            "rule",
+            "apply an action to the lines of output matching a regex, e.g. \"drop ^Saved\" (actions: drop, forward:CHAT_ID[,CHAT_ID], highlight[:EMOJI], alert) (can be repeated)",
+            "\"ACTION REGEX\"",
+        );
+        opts.optmulti(
+            "",
+            "trigger",
+            "run a console command when a line of output other than chat matches a regex, which should be anchored with ^, e.g. \"^Game over! => host\" (the command can use the captured groups as $1 or ${name}) (can be repeated)",
+            "\"REGEX => COMMAND\"",
+        );
+        opts.optopt(
+            "",
+            "trigger-cooldown",
+            "set the least seconds between two runs of a trigger (default: 30)",
+            "SECONDS",
+        );
+        opts.optopt(
+            "",
+            "trigger-limit",
+            "set the most runs of a trigger in an hour (default: 20)",
+            "COUNT",
+        );
+        opts.optopt(
+            "",
+            "collapse-window",
+            "collapse the copies of a line that follow it into one line with a (repeated ×N) suffix counting the first copy, sent at least this often (default: 0, off)",
+            "SECONDS",
+        );
+        opts.optflag(
+            "",
+            "collapse-numbers",
+            "also collapse lines that differ only in their numbers",
+        );
         opts.optflag("h", "help", "print this help menu");
 
         let usage = opts.usage(&format!("Usage: {} [options] SERVER_FILE", args[0]));
 
-        let matches = opts
-            .parse(&args[1..])
-            .or_else(|e| return Err(e.to_string()))?;
+        let matches = opts.parse(&args[1..]).map_err(|e| e.to_string())?;
 
         if matches.opt_present("h") {
             return Err(usage);
@@ -79,12 +277,206 @@ impl Config {
             .parse::<u16>()
             .unwrap_or(0);
 
+        let format = match matches.opt_str("f") {
+            Some(format) => format.parse()?,
+            None => OutputFormat::default(),
+        };
+
+        let file_threshold = parse_opt(&matches, "file-threshold", 16384)?;
+
+        let confirm_commands = matches
+            .opt_str("confirm")
+            .unwrap_or_else(|| String::from("stop,gameover,ban,load"))
+            .split(',')
+            .map(str::trim)
+            .filter(|command| !command.is_empty())
+            .map(String::from)
+            .collect();
+
+        let confirm_timeout = Duration::from_secs(parse_opt(&matches, "confirm-timeout", 60)?);
+
+        let self_commands = matches
+            .opt_str("self-commands")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|command| !command.is_empty())
+            .map(String::from)
+            .collect();
+
+        let status_chats = matches
+            .opt_strs("status-chat")
+            .iter()
+            .map(|chat_id| {
+                chat_id
+                    .parse()
+                    .map_err(|_| format!("Invalid value of --status-chat: {}", chat_id))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let status_interval = Duration::from_secs(parse_opt(&matches, "status-interval", 60)?);
+
+        if status_interval.is_zero() {
+            return Err(String::from("Invalid value of --status-interval: 0"));
+        }
+
+        let bridge_chat = match matches.opt_str("bridge-chat") {
+            Some(chat_id) => Some(
+                chat_id
+                    .parse()
+                    .map_err(|_| format!("Invalid value of --bridge-chat: {}", chat_id))?,
+            ),
+            None => None,
+        };
+
+        let data = matches
+            .opt_str("d")
+            .unwrap_or_else(|| String::from("teledustry.db"));
+
+        let idle_policy = parse_opt(&matches, "idle-policy", IdlePolicy::default())?;
+        let idle_timeout = match parse_opt::<u64>(&matches, "idle-minutes", 5)?.checked_mul(60) {
+            Some(idle_timeout) => Duration::from_secs(idle_timeout),
+            None => {
+                return Err(format!(
+                    "Invalid value of --idle-minutes: {}",
+                    matches.opt_str("idle-minutes").unwrap_or_default()
+                ))
+            }
+        };
+
+        let vote_duration = parse_opt(&matches, "vote-duration", 60)?;
+
+        // Telegram closes polls by themselves only within this range
+        if !(5..=600).contains(&vote_duration) {
+            return Err(format!(
+                "Invalid value of --vote-duration: {}",
+                vote_duration
+            ));
+        }
+
+        let vote_duration = Duration::from_secs(vote_duration);
+
+        let watchdog_interval = Duration::from_secs(parse_opt(&matches, "watchdog-interval", 0)?);
+        let watchdog_timeout = Duration::from_secs(parse_opt(&matches, "watchdog-timeout", 10)?);
+        let watchdog_misses = parse_opt(&matches, "watchdog-misses", 3)?;
+        let thread_dump = parse_opt(&matches, "thread-dump", ThreadDump::default())?;
+
+        if watchdog_timeout.is_zero() {
+            return Err(String::from("Invalid value of --watchdog-timeout: 0"));
+        }
+
+        if watchdog_misses == 0 {
+            return Err(String::from("Invalid value of --watchdog-misses: 0"));
+        }
+
+        let watchdog = if watchdog_interval.is_zero() {
+            None
+        } else {
+            Some(Watchdog {
+                interval: watchdog_interval,
+                timeout: watchdog_timeout,
+                misses: watchdog_misses,
+                thread_dump,
+            })
+        };
+
+        let alert_chats = matches
+            .opt_strs("alert-chat")
+            .iter()
+            .map(|chat_id| {
+                chat_id
+                    .parse()
+                    .map_err(|_| format!("Invalid value of --alert-chat: {}", chat_id))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let crash_lines = parse_opt(&matches, "crash-lines", 200)?;
+
+        let scrollback = parse_opt(&matches, "scrollback", 1000)?;
+
+        let log_dir = matches
+            .opt_str("log-dir")
+            .unwrap_or_else(|| String::from("logs"));
+        let log_size = match parse_opt::<u64>(&matches, "log-size", 10)?.checked_mul(1024 * 1024) {
+            Some(log_size) if log_size > 0 => log_size,
+            _ => {
+                return Err(format!(
+                    "Invalid value of --log-size: {}",
+                    matches.opt_str("log-size").unwrap_or_default()
+                ))
+            }
+        };
+        let log_retention = parse_opt(&matches, "log-retention", 10)?;
+
+        let rules = matches
+            .opt_strs("rule")
+            .iter()
+            .map(|rule| {
+                rule.parse()
+                    .map_err(|e| format!("Invalid value of --rule: {}", e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let triggers = matches
+            .opt_strs("trigger")
+            .iter()
+            .map(|trigger| {
+                trigger
+                    .parse()
+                    .map_err(|e| format!("Invalid value of --trigger: {}", e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let trigger_cooldown = Duration::from_secs(parse_opt(&matches, "trigger-cooldown", 30)?);
+        let trigger_limit = parse_opt(&matches, "trigger-limit", 20)?;
+
+        if trigger_limit == 0 {
+            return Err(String::from("Invalid value of --trigger-limit: 0"));
+        }
+
+        let collapse_window = Duration::from_secs(parse_opt(&matches, "collapse-window", 0)?);
+        let collapse_numbers = matches.opt_present("collapse-numbers");
+
         Ok(Self {
             token,
             user,
             proxy,
             webhook,
+            format,
+            file_threshold,
+            collapse_window,
+            collapse_numbers,
+            confirm_commands,
+            confirm_timeout,
+            self_commands,
+            status_chats,
+            status_interval,
+            bridge_chat,
+            data,
+            idle_policy,
+            idle_timeout,
+            vote_duration,
+            watchdog,
+            alert_chats,
+            crash_lines,
+            scrollback,
+            log_dir,
+            log_size,
+            log_retention,
+            rules,
+            triggers,
+            trigger_cooldown,
+            trigger_limit,
             file,
         })
     }
 }
+
+fn parse_opt<T: FromStr>(matches: &Matches, name: &str, default: T) -> Result<T, String> {
+    match matches.opt_str(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value of --{}: {}", name, value)),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fs, process::ExitStatus, time::SystemTime};
+
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+
+// The JVM writes its fatal error log to the working directory as `hs_err_pid<pid>.log`
+const HS_ERR_PREFIX: &str = "hs_err_pid";
+const HS_ERR_SUFFIX: &str = ".log";
+
+pub fn describe(status: &ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exited with code {}", code);
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = status.signal() {
+        return format!("was killed by signal {}", signal);
+    }
+
+    String::from("exited")
+}
+
+// The fatal error logs written since the game process was started, with their file names
+pub fn hs_err_logs(since: SystemTime) -> Vec<(String, String)> {
+    let entries = match fs::read_dir(".") {
+        Ok(entries) => entries,
+        Err(e) => {
+            log!("Failed to look for JVM fatal error logs: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut logs = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+
+            if !name.starts_with(HS_ERR_PREFIX) || !name.ends_with(HS_ERR_SUFFIX) {
+                return None;
+            }
+
+            if entry.metadata().ok()?.modified().ok()? < since {
+                return None;
+            }
+
+            let log = fs::read(entry.path()).ok()?;
+            Some((name, String::from_utf8_lossy(&log).into_owned()))
+        })
+        .collect::<Vec<_>>();
+
+    logs.sort();
+    logs
+}
+
+pub fn report<'a>(
+    status: &str,
+    hs_err_logs: &[(String, String)],
+    lines: impl Iterator<Item = &'a String>,
+) -> String {
+    let mut report = format!("The game server {}\n", status);
+
+    for (name, log) in hs_err_logs {
+        report.push_str(&format!("\n==> {} <==\n{}", name, log));
+
+        if !log.ends_with('\n') {
+            report.push('\n');
+        }
+    }
+
+    report.push_str("\n==> Last lines of the output <==\n");
+
+    for line in lines {
+        report.push_str(line);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn describes_exit_status() {
+        assert_eq!(
+            describe(&ExitStatus::from_raw(1 << 8)),
+            "exited with code 1"
+        );
+        assert_eq!(describe(&ExitStatus::from_raw(9)), "was killed by signal 9");
+    }
+
+    #[test]
+    fn puts_logs_before_output() {
+        let logs = vec![(String::from("hs_err_pid42.log"), String::from("# SIGSEGV"))];
+        let lines = [
+            String::from("Server loaded.\n"),
+            String::from("Map loaded.\n"),
+        ];
+
+        assert_eq!(
+            report("exited with code 134", &logs, lines.iter()),
+            "The game server exited with code 134\n\n==> hs_err_pid42.log <==\n# SIGSEGV\n\n==> Last lines of the output <==\nServer loaded.\nMap loaded.\n"
+        );
+    }
+}
@@ -0,0 +1,488 @@
+use crate::{
+    chunk::{self, MESSAGE_LIMIT},
+    format::OutputFormat,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+    sync::Arc,
+    time::Duration,
+};
+use tgbot::{
+    methods::{EditMessageText, SendDocument, SendMessage},
+    types::{InputFile, InputFileInfo, InputFileReader, Integer},
+    Api, ExecuteError,
+};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::{self, Instant},
+};
+
+const CAPTION_LIMIT: usize = 1024;
+const PREVIEW_LINES: usize = 5;
+
+// Telegram allows about 30 messages per second in total, one message per second in a single
+// chat and 20 messages per minute in a group
+const GLOBAL_INTERVAL: Duration = Duration::from_millis(34);
+const CHAT_INTERVAL: Duration = Duration::from_secs(1);
+const GROUP_INTERVAL: Duration = Duration::from_secs(3);
+
+// The most messages queued for a chat, beyond which the oldest ones are dropped
+const QUEUE_LIMIT: usize = 1000;
+
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq)]
+enum Outgoing {
+    Text(String),
+    // The file name and the text
+    Document(String, String),
+    // Appended to the message that is being edited in place
+    Stream(String),
+    EndStream,
+    // The number of lines dropped while the queue was full
+    Dropped(usize),
+}
+
+enum Action {
+    Send(String),
+    Edit(Integer, String),
+    SendDocument(String, String),
+}
+
+type Queue = mpsc::UnboundedSender<(Outgoing, OutputFormat)>;
+
+#[derive(Clone)]
+pub struct Delivery {
+    api: Api,
+    queues: Arc<Mutex<HashMap<i64, Queue>>>,
+    next_global_slot: Arc<Mutex<Instant>>,
+}
+
+impl Delivery {
+    pub fn init(api: Api) -> Self {
+        Self {
+            api,
+            queues: Arc::new(Mutex::new(HashMap::new())),
+            next_global_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub async fn send(&self, chat_id: i64, text: String, format: OutputFormat) {
+        // The text is split once here, so the queue only holds texts that fit in a message
+        for chunk in chunk::split(&text, MESSAGE_LIMIT) {
+            self.enqueue(chat_id, Outgoing::Text(chunk), format).await;
+        }
+    }
+
+    // Sends the text as a file attachment, with its first few lines as the caption
+    pub async fn send_document(
+        &self,
+        chat_id: i64,
+        name: String,
+        text: String,
+        format: OutputFormat,
+    ) {
+        self.enqueue(chat_id, Outgoing::Document(name, text), format)
+            .await;
+    }
+
+    // Appends the text to the last streamed message of the chat by editing it, until the message
+    // is full and a new one has to be started
+    pub async fn stream(&self, chat_id: i64, text: String, format: OutputFormat) {
+        for chunk in chunk::split(&text, MESSAGE_LIMIT) {
+            self.enqueue(chat_id, Outgoing::Stream(chunk), format).await;
+        }
+    }
+
+    pub async fn end_stream(&self, chat_id: i64) {
+        self.enqueue(chat_id, Outgoing::EndStream, OutputFormat::default())
+            .await;
+    }
+
+    async fn enqueue(&self, chat_id: i64, outgoing: Outgoing, format: OutputFormat) {
+        let mut queues = self.queues.lock().await;
+
+        let outgoing = match queues.get(&chat_id) {
+            Some(queue) => match queue.send((outgoing, format)) {
+                Ok(_) => return,
+                Err(e) => e.0,
+            },
+            None => (outgoing, format),
+        };
+
+        let (queue, receiver) = mpsc::unbounded_channel();
+        queue.send(outgoing).unwrap_or(());
+        queues.insert(chat_id, queue);
+
+        tokio::spawn(self.clone().run(chat_id, receiver));
+    }
+
+    async fn run(
+        self,
+        chat_id: i64,
+        mut receiver: mpsc::UnboundedReceiver<(Outgoing, OutputFormat)>,
+    ) {
+        let interval = if chat_id < 0 {
+            GROUP_INTERVAL
+        } else {
+            CHAT_INTERVAL
+        };
+
+        let mut next_chat_slot = Instant::now();
+        let mut pending = VecDeque::new();
+
+        // The message being edited in place and its unrendered text
+        let mut live: Option<(Integer, String)> = None;
+
+        loop {
+            if pending.is_empty() {
+                match receiver.recv().await {
+                    Some(outgoing) => pending.push_back(outgoing),
+                    None => return,
+                }
+            }
+
+            if let Some((Outgoing::EndStream, _)) = pending.front() {
+                pending.pop_front();
+                live = None;
+                continue;
+            }
+
+            // The queue is drained while the chat is throttled, so it can not grow past the limit
+            let sleep = time::sleep_until(next_chat_slot);
+            tokio::pin!(sleep);
+
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    Some(outgoing) = receiver.recv() => {
+                        pending.push_back(outgoing);
+                        trim(&mut pending);
+                    }
+                }
+            }
+
+            let live_len = live.as_ref().map_or(0, |(_, text)| chunk::utf16_len(text));
+
+            let (outgoing, format) = match next(&mut pending, MESSAGE_LIMIT - live_len) {
+                Some(outgoing) => outgoing,
+                None => continue,
+            };
+
+            let action = match &outgoing {
+                Outgoing::Text(text) => Action::Send(text.clone()),
+                Outgoing::Dropped(count) => Action::Send(format!("… {} lines dropped", count)),
+                Outgoing::Document(name, text) => Action::SendDocument(name.clone(), text.clone()),
+                Outgoing::Stream(text) => match &live {
+                    Some((message_id, live_text))
+                        if live_len + chunk::utf16_len(text) <= MESSAGE_LIMIT =>
+                    {
+                        Action::Edit(*message_id, format!("{}{}", live_text, text))
+                    }
+                    _ => Action::Send(text.clone()),
+                },
+                Outgoing::EndStream => {
+                    live = None;
+                    continue;
+                }
+            };
+
+            match self.send_with_retry(chat_id, &action, format).await {
+                Ok(message_id) => match action {
+                    Action::Send(text) | Action::Edit(_, text)
+                        if matches!(outgoing, Outgoing::Stream(_)) =>
+                    {
+                        live = message_id.map(|message_id| (message_id, text));
+                    }
+                    // The output streamed afterwards goes below this message
+                    Action::Send(_) => live = None,
+                    _ => (),
+                },
+                Err(Some(retry_after)) => {
+                    pending.push_front((outgoing, format));
+                    next_chat_slot = Instant::now() + retry_after;
+                    continue;
+                }
+                Err(None) => {
+                    if let Outgoing::Stream(_) = outgoing {
+                        live = None;
+                    }
+                }
+            }
+
+            next_chat_slot = Instant::now() + interval;
+        }
+    }
+
+    // Returns the time to wait before the message can be sent again if Telegram rejected it
+    // because of flood control
+    async fn send_with_retry(
+        &self,
+        chat_id: i64,
+        action: &Action,
+        format: OutputFormat,
+    ) -> Result<Option<Integer>, Option<Duration>> {
+        let mut backoff = INITIAL_BACKOFF;
+
+        for attempt in 1..=MAX_RETRIES {
+            self.wait_global_slot().await;
+
+            match self.execute(chat_id, action, format).await {
+                Ok(message_id) => return Ok(message_id),
+                Err(ExecuteError::Response(e)) => {
+                    if let Some(retry_after) = e.retry_after() {
+                        log!(
+                            "Flood control exceeded for chat {}, retrying in {}s",
+                            chat_id,
+                            retry_after
+                        );
+                        return Err(Some(Duration::from_secs(retry_after.max(1) as u64)));
+                    }
+
+                    log!("Failed to send the output to chat {}: {}", chat_id, e);
+                    return Err(None);
+                }
+                Err(ExecuteError::Reqwest(e)) => {
+                    log!(
+                        "Failed to send the output to chat {} (attempt {}/{}): {}",
+                        chat_id,
+                        attempt,
+                        MAX_RETRIES,
+                        e
+                    );
+
+                    if attempt < MAX_RETRIES {
+                        time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                }
+                Err(e) => {
+                    log!("Failed to send the output to chat {}: {}", chat_id, e);
+                    return Err(None);
+                }
+            }
+        }
+
+        Err(None)
+    }
+
+    // Returns the id of the message if a new one was sent
+    async fn execute(
+        &self,
+        chat_id: i64,
+        action: &Action,
+        format: OutputFormat,
+    ) -> Result<Option<Integer>, ExecuteError> {
+        match action {
+            Action::Send(text) => {
+                let mut send_message = SendMessage::new(chat_id, format.render(text));
+
+                if let Some(parse_mode) = format.parse_mode() {
+                    send_message = send_message.parse_mode(parse_mode);
+                }
+
+                let message = self.api.execute(send_message).await?;
+                Ok(Some(message.id))
+            }
+            Action::Edit(message_id, text) => {
+                let mut edit_message_text =
+                    EditMessageText::new(chat_id, *message_id, format.render(text));
+
+                if let Some(parse_mode) = format.parse_mode() {
+                    edit_message_text = edit_message_text.parse_mode(parse_mode);
+                }
+
+                self.api.execute(edit_message_text).await?;
+                Ok(Some(*message_id))
+            }
+            Action::SendDocument(name, text) => {
+                let document = InputFileReader::new(Cursor::new(text.clone().into_bytes()))
+                    .info(InputFileInfo::new(name));
+
+                let mut send_document = SendDocument::new(chat_id, InputFile::reader(document))
+                    .caption(preview(text, format));
+
+                if let Some(parse_mode) = format.parse_mode() {
+                    send_document = send_document.parse_mode(parse_mode);
+                }
+
+                let message = self.api.execute(send_document).await?;
+                Ok(Some(message.id))
+            }
+        }
+    }
+
+    async fn wait_global_slot(&self) {
+        let slot = {
+            let mut next_global_slot = self.next_global_slot.lock().await;
+            let slot = (*next_global_slot).max(Instant::now());
+            *next_global_slot = slot + GLOBAL_INTERVAL;
+            slot
+        };
+
+        time::sleep_until(slot).await;
+    }
+}
+
+// Takes the next message to send. Texts streamed while the chat was throttled are merged, using
+// the latest format requested for this chat, as long as they fit in the `room` left in the live
+// message. Texts sent one by one are merged into a single message as long as it fits
+fn next(
+    pending: &mut VecDeque<(Outgoing, OutputFormat)>,
+    room: usize,
+) -> Option<(Outgoing, OutputFormat)> {
+    if let Some((Outgoing::Text(_), _)) = pending.front() {
+        let (mut text, format) = match pending.pop_front() {
+            Some((Outgoing::Text(text), format)) => (text, format),
+            _ => return None,
+        };
+        let mut len = chunk::utf16_len(&text);
+
+        while let Some((Outgoing::Text(next_text), next_format)) = pending.front() {
+            let next_len = chunk::utf16_len(next_text);
+
+            if *next_format != format || len + 1 + next_len > MESSAGE_LIMIT {
+                break;
+            }
+
+            text.push('\n');
+            text.push_str(next_text);
+            len += 1 + next_len;
+            pending.pop_front();
+        }
+
+        Some((Outgoing::Text(text), format))
+    } else if let Some((Outgoing::Stream(_), _)) = pending.front() {
+        let mut text = String::new();
+        let mut len = 0;
+        let mut room = room;
+        let mut format = OutputFormat::default();
+
+        while let Some((Outgoing::Stream(next_text), next_format)) = pending.front() {
+            let next_len = chunk::utf16_len(next_text);
+
+            if len + next_len > room {
+                if len > 0 {
+                    break;
+                }
+
+                // The text does not fit in the live message, so it starts a new one
+                room = MESSAGE_LIMIT;
+            }
+
+            text.push_str(next_text);
+            len += next_len;
+            format = *next_format;
+            pending.pop_front();
+        }
+
+        Some((Outgoing::Stream(text), format))
+    } else {
+        pending.pop_front()
+    }
+}
+
+// Drops the oldest messages beyond the limit, leaving a note of how many lines were dropped in
+// their place
+fn trim(pending: &mut VecDeque<(Outgoing, OutputFormat)>) {
+    if pending.len() <= QUEUE_LIMIT {
+        return;
+    }
+
+    let mut dropped = 0;
+
+    // One more is dropped to make room for the note
+    while pending.len() >= QUEUE_LIMIT || matches!(pending.front(), Some((Outgoing::Dropped(_), _)))
+    {
+        dropped += match pending.pop_front() {
+            Some((
+                Outgoing::Text(text) | Outgoing::Stream(text) | Outgoing::Document(_, text),
+                _,
+            )) => text.lines().count().max(1),
+            Some((Outgoing::Dropped(count), _)) => count,
+            _ => 0,
+        };
+    }
+
+    pending.push_front((Outgoing::Dropped(dropped), OutputFormat::default()));
+}
+
+fn preview(text: &str, format: OutputFormat) -> String {
+    let lines = text
+        .split_inclusive('\n')
+        .take(PREVIEW_LINES)
+        .collect::<String>();
+
+    // Telegram measures the text after parsing the HTML, so the tags and escapes do not count
+    let preview = chunk::split(&lines, CAPTION_LIMIT - chunk::utf16_len("\n…"))
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+
+    format!("{}\n…", format.render(preview.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(items: Vec<Outgoing>) -> VecDeque<(Outgoing, OutputFormat)> {
+        items
+            .into_iter()
+            .map(|outgoing| (outgoing, OutputFormat::default()))
+            .collect()
+    }
+
+    #[test]
+    fn merges_queued_texts() {
+        let mut pending = queue(vec![
+            Outgoing::Text(String::from("a")),
+            Outgoing::Text(String::from("b")),
+            Outgoing::Text("c".repeat(MESSAGE_LIMIT - 3)),
+            Outgoing::Stream(String::from("d\n")),
+            Outgoing::Stream(String::from("e\n")),
+        ]);
+
+        let next_outgoing = |pending: &mut VecDeque<_>, room| next(pending, room).unwrap().0;
+
+        assert_eq!(
+            next_outgoing(&mut pending, MESSAGE_LIMIT),
+            Outgoing::Text(String::from("a\nb"))
+        );
+        assert_eq!(
+            next_outgoing(&mut pending, MESSAGE_LIMIT),
+            Outgoing::Text("c".repeat(MESSAGE_LIMIT - 3))
+        );
+        // Only the first text fits in the live message
+        assert_eq!(
+            next_outgoing(&mut pending, 2),
+            Outgoing::Stream(String::from("d\n"))
+        );
+        assert_eq!(
+            next_outgoing(&mut pending, 0),
+            Outgoing::Stream(String::from("e\n"))
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_messages() {
+        let mut pending = queue(
+            (0..QUEUE_LIMIT + 1)
+                .map(|_| Outgoing::Stream(String::from("a\nb\n")))
+                .collect(),
+        );
+
+        trim(&mut pending);
+        assert_eq!(pending.len(), QUEUE_LIMIT);
+        assert_eq!(pending[0].0, Outgoing::Dropped(4));
+
+        pending.push_back((Outgoing::Text(String::from("c")), OutputFormat::default()));
+        trim(&mut pending);
+        assert_eq!(pending.len(), QUEUE_LIMIT);
+        assert_eq!(pending[0].0, Outgoing::Dropped(6));
+        assert_eq!(pending.back().unwrap().0, Outgoing::Text(String::from("c")));
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+const UNITS: [(char, u64); 5] = [
+    ('w', 7 * 24 * 60 * 60),
+    ('d', 24 * 60 * 60),
+    ('h', 60 * 60),
+    ('m', 60),
+    ('s', 1),
+];
+
+// Parses durations like `30m`, `2h` or `1d12h`
+pub fn parse(text: &str) -> Option<Duration> {
+    let mut seconds = 0u64;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            let (_, unit) = UNITS.iter().find(|(name, _)| *name == c)?;
+            seconds = seconds.checked_add(number.parse::<u64>().ok()?.checked_mul(*unit)?)?;
+            number.clear();
+        }
+    }
+
+    if !number.is_empty() || seconds == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+// Formats the duration with its two largest units, e.g. `1d 12h`
+pub fn format(duration: Duration) -> String {
+    let mut seconds = duration.as_secs();
+
+    let parts = UNITS
+        .iter()
+        .filter_map(|(name, unit)| {
+            let count = seconds / unit;
+            seconds %= unit;
+            (count > 0).then(|| format!("{}{}", count, name))
+        })
+        .take(2)
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        String::from("0s")
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse("1d12h"), Some(Duration::from_secs(36 * 60 * 60)));
+        assert_eq!(parse("2w"), Some(Duration::from_secs(14 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("30"), None);
+        assert_eq!(parse("0h"), None);
+        assert_eq!(parse("1y"), None);
+        assert_eq!(parse("h"), None);
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format(Duration::from_secs(36 * 60 * 60 + 59)), "1d 12h");
+        assert_eq!(format(Duration::from_secs(90)), "1m 30s");
+        assert_eq!(format(Duration::from_secs(0)), "0s");
+    }
+}
@@ -0,0 +1,253 @@
+use crate::parse;
+
+// Something that happened in the game, detected from a line of the output that is not a part of a
+// response to a command
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Chat {
+        player: String,
+        message: String,
+    },
+    Join {
+        player: String,
+        uuid: String,
+    },
+    // Older servers do not log the UUID of a player who left
+    Leave {
+        player: String,
+        uuid: Option<String>,
+    },
+    // A map was hosted or a save loaded. The map is only known when the server selects the next map
+    // itself after a game over
+    GameStart {
+        map: Option<String>,
+    },
+    // `wave` is reported when the rules have waves, `winner` otherwise
+    GameOver {
+        wave: Option<u32>,
+        winner: Option<String>,
+    },
+    Stop,
+}
+
+impl Event {
+    // Whether the event is the server's own choice of the next map, which the playlist replaces
+    // while it is in use
+    pub fn is_replaced_by_playlist(&self) -> bool {
+        matches!(self, Event::GameStart { map: Some(_) })
+    }
+}
+
+// Only the lines logged at the info level are events, so a warning or an exception is never taken
+// for chat
+pub fn parse(level: Option<char>, line: &str) -> Option<Event> {
+    if level != Some('I') {
+        return None;
+    }
+
+    let line = line.trim_end();
+
+    // Chat is matched first, so a message such as `Anuke has connected. [uuid]` stays chat instead
+    // of passing for a join. Chat messages are logged as `name: message`. Messages sent with `say`
+    // are logged as `Server: message` and are not chat from a player
+    if let Some((player, message)) = line.split_once(": ") {
+        let player = parse::strip_colors(player);
+
+        if player.is_empty() || player == "Server" || message.is_empty() {
+            return None;
+        }
+
+        return Some(Event::Chat {
+            player,
+            message: parse::strip_colors(message),
+        });
+    }
+
+    match line {
+        "Map loaded." | "Save loaded." => return Some(Event::GameStart { map: None }),
+        "Stopped server." => return Some(Event::Stop),
+        _ => (),
+    }
+
+    if let Some(map) = line
+        .strip_prefix("Selected next map to be ")
+        .and_then(|map| map.strip_suffix('.'))
+    {
+        return Some(Event::GameStart {
+            map: Some(parse::strip_colors(map)),
+        });
+    }
+
+    // `Game over! Reached wave 12 with ...` or `Game over! Team crux is victorious with ...`
+    if let Some(rest) = line.strip_prefix("Game over! ") {
+        let wave = rest
+            .strip_prefix("Reached wave ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|wave| wave.parse().ok());
+        let winner = rest
+            .strip_prefix("Team ")
+            .and_then(|rest| rest.split_once(" is victorious"))
+            .map(|(team, _)| team.to_owned());
+
+        return Some(Event::GameOver { wave, winner });
+    }
+
+    // Joins are logged as `name has connected. [uuid]` and leaves as
+    // `name has disconnected. [uuid] (reason)`
+    if let Some((player, rest)) = line.rsplit_once(" has connected. [") {
+        return Some(Event::Join {
+            player: parse::strip_colors(player),
+            uuid: rest.strip_suffix(']')?.to_owned(),
+        });
+    }
+
+    if let Some((player, rest)) = line.rsplit_once(" has disconnected.") {
+        let uuid = rest
+            .trim_start()
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .map(|(uuid, _)| uuid.to_owned());
+
+        return Some(Event::Leave {
+            player: parse::strip_colors(player),
+            uuid,
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: Option<char> = Some('I');
+
+    #[test]
+    fn parses_chat() {
+        assert_eq!(
+            parse(INFO, "[royal]Anuke[]: [red]hello: world\n"),
+            Some(Event::Chat {
+                player: String::from("Anuke"),
+                message: String::from("hello: world"),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_joins_and_leaves() {
+        assert_eq!(
+            parse(INFO, "Anuke has connected. [6PBz8jUBWqgAAAAAjkP5Ug==]\n"),
+            Some(Event::Join {
+                player: String::from("Anuke"),
+                uuid: String::from("6PBz8jUBWqgAAAAAjkP5Ug=="),
+            })
+        );
+        assert_eq!(
+            parse(
+                INFO,
+                "Anuke has disconnected. [6PBz8jUBWqgAAAAAjkP5Ug==] (closed)\n"
+            ),
+            Some(Event::Leave {
+                player: String::from("Anuke"),
+                uuid: Some(String::from("6PBz8jUBWqgAAAAAjkP5Ug==")),
+            })
+        );
+        assert_eq!(
+            parse(INFO, "Anuke has disconnected.\n"),
+            Some(Event::Leave {
+                player: String::from("Anuke"),
+                uuid: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_game_events() {
+        assert_eq!(
+            parse(INFO, "Map loaded.\n"),
+            Some(Event::GameStart { map: None })
+        );
+        assert_eq!(
+            parse(INFO, "Selected next map to be Frozen_Forest.\n"),
+            Some(Event::GameStart {
+                map: Some(String::from("Frozen_Forest")),
+            })
+        );
+        assert_eq!(
+            parse(
+                INFO,
+                "Game over! Reached wave 12 with 3 players online on map Frozen_Forest.\n"
+            ),
+            Some(Event::GameOver {
+                wave: Some(12),
+                winner: None,
+            })
+        );
+        assert_eq!(
+            parse(
+                INFO,
+                "Game over! Team crux is victorious with 2 players online on map Veins.\n"
+            ),
+            Some(Event::GameOver {
+                wave: None,
+                winner: Some(String::from("crux")),
+            })
+        );
+        assert_eq!(parse(INFO, "Stopped server.\n"), Some(Event::Stop));
+    }
+
+    #[test]
+    fn ignores_server_messages() {
+        assert_eq!(parse(INFO, "Server: [TG] someone: hi\n"), None);
+        assert_eq!(parse(INFO, "Opened a server on port 6567.\n"), None);
+    }
+
+    #[test]
+    fn ignores_spoofed_events() {
+        assert_eq!(
+            parse(
+                INFO,
+                "Mallory: Anuke has connected. [6PBz8jUBWqgAAAAAjkP5Ug==]\n"
+            ),
+            Some(Event::Chat {
+                player: String::from("Mallory"),
+                message: String::from("Anuke has connected. [6PBz8jUBWqgAAAAAjkP5Ug==]"),
+            })
+        );
+        assert_eq!(
+            parse(INFO, "Mallory: Game over! Team crux is victorious\n"),
+            Some(Event::Chat {
+                player: String::from("Mallory"),
+                message: String::from("Game over! Team crux is victorious"),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_other_levels() {
+        assert_eq!(
+            parse(
+                Some('E'),
+                "java.lang.RuntimeException: Failed to load the map\n"
+            ),
+            None
+        );
+        assert_eq!(parse(Some('W'), "Unknown block: conveyor-x\n"), None);
+        assert_eq!(
+            parse(None, "Caused by: java.io.IOException: Broken pipe\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn replaces_only_the_server_rotation() {
+        // The map the server selects itself is not hosted while a playlist is in use
+        let rotation = parse(INFO, "Selected next map to be Frozen_Forest.\n").unwrap();
+        assert!(rotation.is_replaced_by_playlist());
+
+        // A map hosted by the playlist, or by hand, starts a match either way
+        let hosted = parse(INFO, "Map loaded.\n").unwrap();
+        assert!(!hosted.is_replaced_by_playlist());
+    }
+}
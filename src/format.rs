@@ -0,0 +1,46 @@
+use std::{fmt, str::FromStr};
+use tgbot::types::ParseMode;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Plain,
+    Monospace,
+}
+
+impl OutputFormat {
+    pub fn render(self, text: &str) -> String {
+        match self {
+            Self::Plain => text.to_owned(),
+            Self::Monospace => format!("<pre>{}</pre>", ParseMode::Html.escape(text)),
+        }
+    }
+
+    pub fn parse_mode(self) -> Option<ParseMode> {
+        match self {
+            Self::Plain => None,
+            Self::Monospace => Some(ParseMode::Html),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "monospace" => Ok(Self::Monospace),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::Monospace => write!(f, "monospace"),
+        }
+    }
+}
@@ -1,141 +1,549 @@
-use crate::config::Config;
+use crate::{config::Config, crash, logfile};
 use std::{
+    collections::VecDeque,
+    mem,
     process::{self, Stdio},
     str,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::Command,
-    sync::mpsc::{Receiver, Sender},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::{
+        mpsc::{Receiver, Sender},
+        oneshot,
+    },
+    time::{self, Instant},
 };
 
+// The delay before restarting a game server that crashed, doubled for each crash on startup in a
+// row
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(10 * 60);
+
+// A crash within this long after the start counts as a crash on startup
+const STARTUP_TIME: Duration = Duration::from_secs(60);
+
+// The crashes on startup in a row after which the crashes are no longer alerted, until the game
+// server runs past its startup again
+const CRASH_ALERTS: u32 = 5;
+
+// The most of a response captured for a query, in bytes
+const CAPTURE_LIMIT: usize = 1024 * 1024;
+
+pub struct Input {
+    pub command: String,
+    // Receives the response instead of the output chats
+    pub response_sender: Option<oneshot::Sender<String>>,
+}
+
+pub enum Output {
+    // A line of the output, and whether it is a part of the response to a command
+    Line {
+        text: String,
+        // The level the line was logged at, e.g. `I` for info, or `None` for the lines logged
+        // without a prefix, such as the rest of a stack trace
+        level: Option<char>,
+        is_response: bool,
+    },
+    // The end of the response to a command
+    End,
+    // Something is wrong with the game process, with a text file to attach, such as a thread dump
+    Alert {
+        text: String,
+        attachment: Option<Attachment>,
+    },
+    // The game process was restarted and is loading again
+    Restart,
+}
+
+pub struct Attachment {
+    pub name: String,
+    pub text: String,
+}
+
+// What the game process is expected to respond to next
+enum Pending {
+    // A command whose response goes to the output chats
+    Output,
+    // A command whose response goes to the sender instead
+    Response(oneshot::Sender<String>),
+    // Only the end of a response, sent by the watchdog to check that the game still reads its input
+    Probe(oneshot::Sender<()>),
+}
+
 pub struct Game;
 
 impl Game {
     pub async fn spawn(
         config: &Config,
-        output_sender: Sender<String>,
-        mut input_receiver: Receiver<String>,
+        output_sender: Sender<Output>,
+        mut input_receiver: Receiver<Input>,
     ) -> Result<(), String> {
-        let mut game = Command::new("java")
-            .arg("-jar")
-            .arg(config.file.as_str())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .kill_on_drop(true)
-            .spawn()
-            .or_else(|e| Err(e.to_string()))?;
-
-        let mut game_stdin = game
-            .stdin
-            .take()
-            .ok_or_else(|| String::from("Failed to create STDIN pipe"))?;
-
-        let input_handler = tokio::spawn(async move {
-            match game_stdin
-                .write("help\nEND_CMD\nEND_CMD\n".as_bytes())
-                .await
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!(
-                        "Failed to communicate with the game process: {}",
-                        e.to_string()
-                    );
-                    process::exit(1);
-                }
+        // The crashes on startup in a row
+        let mut startup_crashes = 0;
+
+        loop {
+            let spawned_at = SystemTime::now();
+
+            let mut game = Command::new("java")
+                .arg("-jar")
+                .arg(config.file.as_str())
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| e.to_string())?;
+
+            let mut game_stdin = game
+                .stdin
+                .take()
+                .ok_or_else(|| String::from("Failed to create STDIN pipe"))?;
+
+            let game_stdout = game
+                .stdout
+                .take()
+                .ok_or_else(|| String::from("Failed to create STDOUT pipe"))?;
+
+            // The commands whose response has not been fully received yet, starting with `help`
+            let pending_commands = Arc::new(Mutex::new(VecDeque::from(vec![Pending::Output])));
+
+            // Collects the output instead of the output chats while it is `Some`
+            let dump = Arc::new(Mutex::new(None));
+
+            // The last lines of the output, for the crash report
+            let recent_lines = Arc::new(Mutex::new(VecDeque::new()));
+
+            let mut output_handler = tokio::spawn(handle_output(
+                game_stdout,
+                Arc::clone(&pending_commands),
+                Arc::clone(&dump),
+                Arc::clone(&recent_lines),
+                config.crash_lines,
+                output_sender.clone(),
+            ));
+
+            // A failed write means the game process exited, which the output handler finds out
+            if let Err(e) = write(&mut game_stdin, "help\nEND_CMD\nEND_CMD\n").await {
+                log!("{}", e);
             }
 
-            while let Some(cmd) = input_receiver.recv().await {
-                match game_stdin
-                    .write(format!("{}\nEND_CMD\nEND_CMD\n", cmd).as_bytes())
-                    .await
-                {
+            let watchdog = config.watchdog;
+            let mut probe_interval = watchdog.map(|watchdog| {
+                time::interval_at(Instant::now() + watchdog.interval, watchdog.interval)
+            });
+            let mut probe: Option<(oneshot::Receiver<()>, Instant)> = None;
+            let mut missed = 0;
+
+            // Whether the game process hung, as opposed to exiting
+            let is_hung = loop {
+                tokio::select! {
+                    input = input_receiver.recv() => match input {
+                        Some(input) => {
+                            if let Err(e) = send_input(&mut game_stdin, &pending_commands, input).await {
+                                log!("{}", e);
+                                break false;
+                            }
+                        }
+                        None => return output_handler.await.map_err(|e| e.to_string()),
+                    },
+                    _ = async { probe_interval.as_mut().unwrap().tick().await },
+                        if probe_interval.is_some() && probe.is_none() =>
+                    {
+                        let (probe_sender, probe_receiver) = oneshot::channel();
+                        pending_commands
+                            .lock()
+                            .unwrap()
+                            .push_back(Pending::Probe(probe_sender));
+
+                        if let Err(e) = write(&mut game_stdin, "END_CMD\nEND_CMD\n").await {
+                            log!("{}", e);
+                            break false;
+                        }
+
+                        let timeout = watchdog.map(|watchdog| watchdog.timeout).unwrap_or_default();
+                        probe = Some((probe_receiver, Instant::now() + timeout));
+                    }
+                    result = async {
+                        let (probe_receiver, deadline) = probe.as_mut().unwrap();
+                        time::timeout_at(*deadline, probe_receiver).await
+                    }, if probe.is_some() => {
+                        probe = None;
+
+                        let misses = watchdog.map(|watchdog| watchdog.misses).unwrap_or_default();
+
+                        match result {
+                            Ok(Ok(_)) => missed = 0,
+                            _ => {
+                                missed += 1;
+                                log!(
+                                    "The game server did not respond to a probe ({}/{})",
+                                    missed, misses
+                                );
+                            }
+                        }
+
+                        if missed >= misses {
+                            break true;
+                        }
+                    }
+                    // The output ends when the game process exits
+                    _ = &mut output_handler => break false,
+                }
+            };
+
+            if is_hung {
+                let mut text = format!(
+                    "The game server has not responded for {} probes in a row and is restarted",
+                    missed
+                );
+
+                let mut attachment = None;
+
+                if let (Some(watchdog), Some(pid)) = (watchdog, game.id()) {
+                    match watchdog.dump_threads(pid, &dump).await {
+                        Ok(Some(threads)) => {
+                            attachment = Some(Attachment {
+                                name: format!("threads-{}.txt", pid),
+                                text: threads,
+                            })
+                        }
+                        Ok(None) => (),
+                        Err(e) => text.push_str(&format!("\nFailed to capture the threads: {}", e)),
+                    }
+                }
+
+                log!("{}", text);
+                send_output(&output_sender, Output::Alert { text, attachment }).await;
+
+                output_handler.abort();
+
+                match game.kill().await {
                     Ok(_) => (),
+                    Err(e) => log!("Failed to kill the game process: {}", e),
+                }
+            } else {
+                let pid = game.id();
+
+                let status = match game.wait().await {
+                    Ok(status) => status,
                     Err(e) => {
-                        eprintln!(
-                            "Failed to communicate with the game process: {}",
-                            e.to_string()
-                        );
+                        log!("Failed to wait for the game process: {}", e);
                         process::exit(1);
                     }
+                };
+
+                // The server was shut down on purpose, e.g. with `exit`
+                if status.success() {
+                    info!("The game server exited");
+                    process::exit(0);
+                }
+
+                let status = crash::describe(&status);
+
+                let report = crash::report(
+                    &status,
+                    &crash::hs_err_logs(spawned_at),
+                    recent_lines.lock().unwrap().iter(),
+                );
+
+                let is_startup = spawned_at
+                    .elapsed()
+                    .map(|elapsed| elapsed < STARTUP_TIME)
+                    .unwrap_or(false);
+
+                if is_startup {
+                    startup_crashes += 1;
+                } else {
+                    startup_crashes = 0;
+                }
+
+                let delay = restart_delay(startup_crashes);
+
+                let mut text = format!(
+                    "The game server {} and is restarted in {}s",
+                    status,
+                    delay.as_secs()
+                );
+
+                if startup_crashes == CRASH_ALERTS {
+                    text.push_str(&format!(
+                        "\nIt crashed on startup {} times in a row. The next crashes are not alerted until it starts up",
+                        startup_crashes
+                    ));
                 }
+
+                log!("{}", text);
+
+                let attachment = Attachment {
+                    name: match pid {
+                        Some(pid) => format!("crash-{}.txt", pid),
+                        None => String::from("crash.txt"),
+                    },
+                    text: report,
+                };
+
+                if startup_crashes <= CRASH_ALERTS {
+                    send_output(
+                        &output_sender,
+                        Output::Alert {
+                            text,
+                            attachment: Some(attachment),
+                        },
+                    )
+                    .await;
+                }
+
+                // Keeps a server that crashes on startup from flooding the alert chats
+                time::sleep(delay).await;
             }
+
+            send_output(&output_sender, Output::Restart).await;
+        }
+    }
+}
+
+// Doubles the delay for each crash on startup in a row after the first
+fn restart_delay(startup_crashes: u32) -> Duration {
+    let factor = 2u32.saturating_pow(startup_crashes.saturating_sub(1));
+
+    RESTART_DELAY
+        .checked_mul(factor)
+        .unwrap_or(MAX_RESTART_DELAY)
+        .min(MAX_RESTART_DELAY)
+}
+
+async fn send_input(
+    game_stdin: &mut ChildStdin,
+    pending_commands: &Mutex<VecDeque<Pending>>,
+    input: Input,
+) -> Result<(), String> {
+    pending_commands
+        .lock()
+        .unwrap()
+        .push_back(match input.response_sender {
+            Some(response_sender) => Pending::Response(response_sender),
+            None => Pending::Output,
         });
 
-        let mut game_stdout = BufReader::new(
-            game.stdout
-                .take()
-                .ok_or_else(|| String::from("Failed to create STDOUT pipe"))?,
-        );
+    write(
+        game_stdin,
+        &format!("{}\nEND_CMD\nEND_CMD\n", input.command),
+    )
+    .await
+}
 
-        let output_handler = tokio::spawn(async move {
-            let mut output = String::new();
+async fn write(game_stdin: &mut ChildStdin, text: &str) -> Result<(), String> {
+    game_stdin
+        .write_all(text.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to communicate with the game process: {}", e))
+}
 
-            let ignore = b"[00-00-0000 00:00:00] [0] "
-                .iter()
-                .cloned()
-                .collect::<Vec<u8>>();
+// Two of these in a row end the response to a command
+const END_INDICATOR: &[u8] = b"[E] Invalid command. Type 'help' for help.\n";
 
-            let mut last_line = Vec::new();
-            last_line.clone_from(&ignore);
+async fn handle_output(
+    game_stdout: impl AsyncRead + Unpin,
+    pending_commands: Arc<Mutex<VecDeque<Pending>>>,
+    dump: Arc<Mutex<Option<String>>>,
+    recent_lines: Arc<Mutex<VecDeque<String>>>,
+    recent_limit: usize,
+    output_sender: Sender<Output>,
+) {
+    let mut game_stdout = BufReader::new(game_stdout);
 
-            let mut buf = Vec::new();
+    let mut captured = String::new();
 
-            while let Ok(_) = game_stdout.read_until(10, &mut buf).await {
-                buf = strip_ansi_escapes::strip(&buf).unwrap_or(ignore.clone());
+    let ignore = b"[00-00-0000 00:00:00] [0] ".to_vec();
 
-                if buf.ends_with(b"[I] Server loaded. Type 'help' for help.\n") {
-                    buf.clear();
-                    continue;
+    let mut last_line = Vec::new();
+    last_line.clone_from(&ignore);
+
+    let mut buf = Vec::new();
+
+    while let Ok(len) = game_stdout.read_until(10, &mut buf).await {
+        if len == 0 {
+            break;
+        }
+
+        buf = strip_ansi_escapes::strip(&buf).unwrap_or(ignore.clone());
+
+        if buf.ends_with(b"[I] Server loaded. Type 'help' for help.\n") {
+            buf.clear();
+            continue;
+        }
+
+        // The threads printed after SIGQUIT are not logged with a timestamp
+        if let Some(dump) = dump.lock().unwrap().as_mut() {
+            dump.push_str(&String::from_utf8_lossy(&buf));
+        }
+
+        if buf.ends_with(END_INDICATOR) && last_line.ends_with(END_INDICATOR) {
+            let pending_command = pending_commands.lock().unwrap().pop_front();
+
+            match pending_command {
+                Some(Pending::Response(response_sender)) => {
+                    response_sender.send(mem::take(&mut captured)).unwrap_or(());
                 }
+                Some(Pending::Probe(probe_sender)) => probe_sender.send(()).unwrap_or(()),
+                _ => send_output(&output_sender, Output::End).await,
+            }
 
-                let end_indicator = b"[E] Invalid command. Type 'help' for help.\n";
+            buf.clear();
+            last_line.clone_from(&ignore);
+        } else {
+            let line = str::from_utf8(last_line.get(26..).unwrap_or_default()).unwrap_or("");
 
-                if buf.ends_with(end_indicator) && last_line.ends_with(end_indicator) {
-                    print!("\n{}", output);
+            // A probe is only sent for the end of its response, which is not shown
+            let is_probe = last_line.ends_with(END_INDICATOR)
+                && matches!(
+                    pending_commands.lock().unwrap().front(),
+                    Some(Pending::Probe(_))
+                );
 
-                    match output_sender.send(output.clone()).await {
-                        Ok(_) => (),
-                        Err(e) => {
-                            eprintln!(
-                                "Failed to communicate with the bot instance: {}",
-                                e.to_string()
-                            );
-                            process::exit(1);
-                        }
-                    }
+            // The ends of the responses are left out, so they do not fill the crash report
+            if last_line != ignore && !is_probe {
+                record(&recent_lines, recent_limit, &last_line);
+            }
 
-                    output.clear();
-                    buf.clear();
-                    last_line.clone_from(&ignore);
-                } else {
-                    if output.len() + last_line.len() > 4096 + 26 {
-                        print!("\n{}", output);
-
-                        match output_sender.send(output.clone()).await {
-                            Ok(_) => (),
-                            Err(e) => {
-                                eprintln!(
-                                    "Failed to communicate with the bot instance: {}",
-                                    e.to_string()
-                                );
-                                process::exit(1);
-                            }
-                        }
-                        output.clear();
-                    }
+            if !line.is_empty() && !is_probe && dump.lock().unwrap().is_none() {
+                print!("{}", line);
+
+                let (is_captured, is_response) = {
+                    let pending_commands = pending_commands.lock().unwrap();
+                    (
+                        matches!(pending_commands.front(), Some(Pending::Response(_))),
+                        matches!(
+                            pending_commands.front(),
+                            Some(Pending::Output | Pending::Response(_))
+                        ),
+                    )
+                };
+
+                // The other lines are logged with the scrollback
+                if is_captured {
+                    logfile::write("query", line);
 
-                    output.push_str(str::from_utf8(&last_line[26..]).unwrap_or(""));
+                    if captured.len() + line.len() <= CAPTURE_LIMIT {
+                        captured.push_str(line);
+                    }
+                } else {
+                    // The prefix reads as `[01-01-2024 00:00:00] [I] `
+                    let level = match (last_line.first(), last_line.get(21..26)) {
+                        (Some(b'['), Some([b' ', b'[', level, b']', b' '])) => Some(*level as char),
+                        _ => None,
+                    };
 
-                    last_line.clone_from(&buf);
-                    buf.clear();
+                    let line = Output::Line {
+                        text: line.to_owned(),
+                        level,
+                        is_response,
+                    };
+                    send_output(&output_sender, line).await;
                 }
             }
-        });
 
-        tokio::try_join!(input_handler, output_handler).or_else(|e| Err(e.to_string()))?;
+            last_line.clone_from(&buf);
+            buf.clear();
+        }
+    }
+
+    // The last line is held until the next one, which never comes once the game process exits
+    if last_line != ignore && !last_line.ends_with(END_INDICATOR) {
+        record(&recent_lines, recent_limit, &last_line);
+    }
+}
+
+// Keeps the line for the crash report
+fn record(recent_lines: &Mutex<VecDeque<String>>, recent_limit: usize, line: &[u8]) {
+    if recent_limit == 0 {
+        return;
+    }
+
+    let mut recent_lines = recent_lines.lock().unwrap();
+
+    if recent_lines.len() >= recent_limit {
+        recent_lines.pop_front();
+    }
+
+    recent_lines.push_back(String::from_utf8_lossy(line).into_owned());
+}
+
+async fn send_output(output_sender: &Sender<Output>, output: Output) {
+    match output_sender.send(output).await {
+        Ok(_) => (),
+        Err(e) => {
+            log!("Failed to communicate with the bot instance: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    const END: &str = "[01-01-2024 00:00:00] [E] Invalid command. Type 'help' for help.\n";
+
+    fn line(text: &str) -> String {
+        format!("[01-01-2024 00:00:00] [I] {}\n", text)
+    }
+
+    #[test]
+    fn backs_off_restarts() {
+        assert_eq!(restart_delay(0), RESTART_DELAY);
+        assert_eq!(restart_delay(1), RESTART_DELAY);
+        assert_eq!(restart_delay(3), RESTART_DELAY * 4);
+        assert_eq!(restart_delay(40), MAX_RESTART_DELAY);
+    }
+
+    #[tokio::test]
+    async fn keeps_order_after_a_late_probe() {
+        // The watchdog stopped waiting for the probe, so its receiver is gone
+        let (probe_sender, _) = oneshot::channel();
+        let (response_sender, response_receiver) = oneshot::channel();
+        let pending_commands = Arc::new(Mutex::new(VecDeque::from(vec![
+            Pending::Probe(probe_sender),
+            Pending::Response(response_sender),
+            Pending::Output,
+        ])));
+
+        let output = [
+            line("Anuke: hi"),
+            END.repeat(2),
+            line("Status: playing"),
+            END.repeat(2),
+            line("Map loaded."),
+            END.repeat(2),
+        ]
+        .concat();
+
+        let (output_sender, mut output_receiver) = mpsc::channel(16);
+        handle_output(
+            output.as_bytes(),
+            pending_commands,
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(VecDeque::new())),
+            0,
+            output_sender,
+        )
+        .await;
+
+        assert_eq!(response_receiver.await.unwrap(), "Status: playing\n");
 
-        Ok(())
+        assert!(matches!(
+            output_receiver.recv().await,
+            Some(Output::Line { text, level: Some('I'), is_response: false })
+                if text == "Anuke: hi\n"
+        ));
+        assert!(matches!(
+            output_receiver.recv().await,
+            Some(Output::Line { text, is_response: true, .. }) if text == "Map loaded.\n"
+        ));
+        assert!(matches!(output_receiver.recv().await, Some(Output::End)));
+        assert!(output_receiver.recv().await.is_none());
     }
 }
@@ -0,0 +1,130 @@
+use crate::{
+    bot::BotUpdateHandler,
+    duration,
+    parse::{self, Status},
+    store::{self, Match},
+};
+use std::time::Duration;
+
+const CSV_HEADER: &str = "id,map,mode,started_at,ended_at,waves,winner,peak_players\n";
+
+// The map of a hosted game is not logged, so it is taken from `status` once the game has started
+pub async fn fill_map(handler: BotUpdateHandler, id: i64) {
+    let status = match handler.query(String::from("status")).await {
+        Some(output) => parse::status(&output),
+        None => None,
+    };
+
+    if let Some(Status::Playing(status)) = status {
+        match handler.context.store.set_match_map(id, &status.map) {
+            Ok(_) => (),
+            Err(e) => log!("Failed to record the map of the match: {}", e),
+        }
+    }
+}
+
+pub fn summary(game: &Match) -> String {
+    let now = store::now();
+    let ago = |time: i64| duration::format(Duration::from_secs((now - time).max(0) as u64));
+
+    let mut text = format!(
+        "#{} {}",
+        game.id,
+        game.map.as_deref().unwrap_or("Unknown map")
+    );
+
+    if let Some(mode) = &game.mode {
+        text.push_str(&format!(" ({})", mode));
+    }
+
+    text.push_str(&format!("\n  Started {} ago", ago(game.started_at)));
+
+    match game.ended_at {
+        Some(ended_at) => text.push_str(&format!(
+            ", lasted {}",
+            duration::format(Duration::from_secs(
+                (ended_at - game.started_at).max(0) as u64
+            ))
+        )),
+        None => text.push_str(", not finished"),
+    }
+
+    if let Some(waves) = game.waves {
+        text.push_str(&format!("\n  Reached wave {}", waves));
+    }
+
+    if let Some(winner) = &game.winner {
+        text.push_str(&format!("\n  Won by {}", winner));
+    }
+
+    text.push_str(&format!("\n  Peak players: {}", game.peak_players));
+
+    text
+}
+
+pub fn csv(matches: &[Match]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+
+    for game in matches {
+        let fields = [
+            game.id.to_string(),
+            game.map.clone().unwrap_or_default(),
+            game.mode.clone().unwrap_or_default(),
+            game.started_at.to_string(),
+            game.ended_at
+                .map(|time| time.to_string())
+                .unwrap_or_default(),
+            game.waves
+                .map(|waves| waves.to_string())
+                .unwrap_or_default(),
+            game.winner.clone().unwrap_or_default(),
+            game.peak_players.to_string(),
+        ];
+
+        let line = fields
+            .iter()
+            .map(|field| escape(field))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        csv.push_str(&line);
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_csv() {
+        let game = Match {
+            id: 1,
+            map: Some(String::from("My \"Map\", v2")),
+            mode: Some(String::from("survival")),
+            started_at: 100,
+            ended_at: Some(200),
+            waves: Some(12),
+            winner: None,
+            peak_players: 3,
+        };
+
+        assert_eq!(
+            csv(&[game]),
+            format!(
+                "{}1,\"My \"\"Map\"\", v2\",survival,100,200,12,,3\n",
+                CSV_HEADER
+            )
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{bot::BotUpdateHandler, delivery::Delivery};
+use std::{fmt, str::FromStr, time::Duration};
+use tokio::time::{self, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The save slot the game is saved to before the server is stopped
+const SAVE_SLOT: &str = "idle";
+
+// What to do with a hosted game when no player has been online for a while
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IdlePolicy {
+    #[default]
+    None,
+    Pause,
+    Stop,
+}
+
+impl FromStr for IdlePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "pause" => Ok(Self::Pause),
+            "stop" => Ok(Self::Stop),
+            _ => Err(format!("Unknown idle policy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for IdlePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Pause => write!(f, "pause"),
+            Self::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum State {
+    Active,
+    // Paused by this policy, to be unpaused when a player joins
+    Paused,
+    // Stopped by this policy, until the server has logged stopping
+    Stopped,
+}
+
+pub async fn run(
+    handler: BotUpdateHandler,
+    delivery: Delivery,
+    policy: IdlePolicy,
+    timeout: Duration,
+) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    let mut state = State::Active;
+    let mut empty_since = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = handler.context.player_joined.notified() => (),
+        }
+
+        let is_hosted = handler.context.current_match.lock().await.is_some();
+        let players = handler.context.online_players.lock().await.len();
+
+        // Nothing is simulated without a hosted game, and a game hosted again starts over
+        if !is_hosted {
+            state = State::Active;
+            empty_since = None;
+            continue;
+        }
+
+        if players > 0 {
+            empty_since = None;
+
+            if state == State::Paused {
+                handler.send_command(String::from("pause off")).await;
+                announce(&handler, &delivery, "A player joined, the game is unpaused").await;
+                state = State::Active;
+            }
+
+            continue;
+        }
+
+        let empty_since = *empty_since.get_or_insert_with(Instant::now);
+
+        if state != State::Active || empty_since.elapsed() < timeout {
+            continue;
+        }
+
+        let minutes = timeout.as_secs() / 60;
+
+        match policy {
+            IdlePolicy::None => (),
+            IdlePolicy::Pause => {
+                handler.send_command(String::from("pause on")).await;
+                announce(
+                    &handler,
+                    &delivery,
+                    &format!(
+                        "No player has been online for {} minutes, the game is paused",
+                        minutes
+                    ),
+                )
+                .await;
+                state = State::Paused;
+            }
+            IdlePolicy::Stop => {
+                handler.send_command(format!("save {}", SAVE_SLOT)).await;
+                handler.send_command(String::from("stop")).await;
+                announce(
+                    &handler,
+                    &delivery,
+                    &format!(
+                        "No player has been online for {} minutes, the game is saved to slot {} and the server is stopped",
+                        minutes, SAVE_SLOT
+                    ),
+                )
+                .await;
+                state = State::Stopped;
+            }
+        }
+    }
+}
+
+async fn announce(handler: &BotUpdateHandler, delivery: &Delivery, text: &str) {
+    for (chat_id, format) in handler.context.output_targets().await {
+        delivery.send(chat_id, text.to_owned(), format).await;
+    }
+}
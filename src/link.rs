@@ -0,0 +1,159 @@
+use crate::{bot::BotUpdateHandler, delivery::Delivery, format::OutputFormat, parse, store::Link};
+use rand::Rng;
+use std::time::Duration;
+use tgbot::types::Integer;
+use tokio::time::Instant;
+
+pub const CODE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Characters that are easy to tell apart when typed in game
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+
+// Mentions of names shorter than this would match ordinary words
+const MIN_MENTION_LEN: usize = 3;
+
+// A `/link` waiting for its code to be typed in game
+pub struct PendingLink {
+    pub user_id: Integer,
+    // The chat `/link` was sent in, where the result is reported
+    pub chat_id: i64,
+    pub created_at: Instant,
+}
+
+pub fn new_code() -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..CODE_LEN)
+        .map(|_| CODE_CHARS[rng.gen_range(0..CODE_CHARS.len())] as char)
+        .collect()
+}
+
+// Binds the player to the Telegram user whose code they typed, and notifies the linked users who
+// are mentioned in the message
+pub async fn handle_chat(
+    handler: BotUpdateHandler,
+    delivery: Delivery,
+    player: String,
+    message: String,
+) {
+    let pending = {
+        let mut link_codes = handler.context.link_codes.lock().await;
+        link_codes.retain(|_, pending| pending.created_at.elapsed() < CODE_TIMEOUT);
+
+        message
+            .split_whitespace()
+            .find_map(|word| link_codes.remove(&word.to_ascii_uppercase()))
+    };
+
+    if let Some(pending) = pending {
+        let text = match link(&handler, &pending, &player).await {
+            Ok(text) => text,
+            Err(e) => {
+                log!("Failed to link {}: {}", player, e);
+                format!("Failed to link {}", player)
+            }
+        };
+
+        delivery
+            .send(pending.chat_id, text, OutputFormat::Plain)
+            .await;
+        return;
+    }
+
+    let links = match handler.context.store.links() {
+        Ok(links) => links,
+        Err(e) => {
+            log!("Failed to read the linked players: {}", e);
+            return;
+        }
+    };
+
+    let message_lowercase = message.to_lowercase();
+
+    for link in links {
+        let name = link.name.to_lowercase();
+
+        if name.chars().count() >= MIN_MENTION_LEN
+            && name != player.to_lowercase()
+            && message_lowercase.contains(&name)
+        {
+            // A private chat has the same id as the user
+            delivery
+                .send(
+                    link.user_id,
+                    format!("{} mentioned you in game: {}", player, message),
+                    OutputFormat::Plain,
+                )
+                .await;
+        }
+    }
+}
+
+// Whether the arguments of a command name the linked player and nothing else, by UUID or name. A
+// leading `id` or `name`, as in `ban id <UUID>`, is allowed
+pub fn targets_own_player(link: &Link, args: &[String]) -> bool {
+    let args = match args.first().map(String::as_str) {
+        Some("id" | "name") => &args[1..],
+        _ => args,
+    };
+    let target = args.join(" ");
+
+    target == link.uuid || parse::strip_colors(&target).eq_ignore_ascii_case(&link.name)
+}
+
+async fn link(
+    handler: &BotUpdateHandler,
+    pending: &PendingLink,
+    player: &str,
+) -> Result<String, String> {
+    let players = match handler.query(String::from("players")).await {
+        Some(output) => parse::players(&output),
+        None => return Err(String::from("The server did not respond")),
+    };
+
+    // The player in the chat line has no color tags, unlike the names listed by `players`
+    let uuid = match players
+        .into_iter()
+        .find(|online| parse::strip_colors(&online.name) == player)
+    {
+        Some(online) => online.uuid,
+        None => return Ok(format!("Player {} is not in game anymore", player)),
+    };
+
+    handler.context.store.add_link(&Link {
+        user_id: pending.user_id,
+        uuid,
+        name: player.to_owned(),
+    })?;
+
+    Ok(format!("Your Telegram account is now linked to {}", player))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_only_own_player() {
+        let link = Link {
+            user_id: 1,
+            uuid: String::from("AAAAAAAAAAAAAAAAAAAAAA=="),
+            name: String::from("Anuke"),
+        };
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert!(targets_own_player(
+            &link,
+            &args(&["AAAAAAAAAAAAAAAAAAAAAA=="])
+        ));
+        assert!(targets_own_player(
+            &link,
+            &args(&["id", "AAAAAAAAAAAAAAAAAAAAAA=="])
+        ));
+        assert!(targets_own_player(&link, &args(&["anuke"])));
+        assert!(!targets_own_player(&link, &args(&["Sk7725"])));
+        assert!(!targets_own_player(&link, &args(&["add", "Anuke"])));
+        assert!(!targets_own_player(&link, &args(&[])));
+    }
+}
@@ -0,0 +1,477 @@
+use crate::store;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+const CURRENT: &str = "teledustry.log";
+const ROTATED_PREFIX: &str = "teledustry-";
+const ROTATED_SUFFIX: &str = ".log.gz";
+
+const DAY: i64 = 24 * 60 * 60;
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+struct Logger {
+    dir: PathBuf,
+    max_size: u64,
+    retention: usize,
+    file: Option<File>,
+    size: u64,
+    // Unix time the current file was started at
+    started_at: i64,
+    // The thread compressing the last rotated file
+    compressing: Option<thread::JoinHandle<()>>,
+}
+
+pub struct LogFile {
+    pub name: String,
+    pub size: u64,
+}
+
+// Starts writing to the log files in the directory. Until then, nothing is written
+pub fn init(dir: &str, max_size: u64, retention: usize) -> Result<(), String> {
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir).map_err(|e| {
+        format!(
+            "Failed to create the log directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+
+    // A file left from the last run is continued if it is from today
+    let started_at = fs::metadata(dir.join(CURRENT))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs() as i64)
+        .unwrap_or_else(store::now);
+
+    let logger = Logger {
+        dir,
+        max_size,
+        retention,
+        file: None,
+        size: 0,
+        started_at,
+        compressing: None,
+    };
+
+    LOGGER
+        .set(Mutex::new(logger))
+        .map_err(|_| String::from("The log files are already in use"))
+}
+
+pub fn write(source: &str, text: &str) {
+    write_at(store::now(), source, text);
+}
+
+// Logs the line with the given Unix time, which the caller may keep to find the line again
+pub fn write_at(time: i64, source: &str, text: &str) {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return,
+    };
+
+    let line = format!(
+        "{} [{}] {}\n",
+        timestamp(time),
+        source,
+        text.trim_end_matches('\n')
+    );
+
+    // Errors are not logged with `log!`, which would write to the log files again
+    if let Err(e) = logger.lock().unwrap().write(time, &line) {
+        eprintln!("Failed to write to the log files: {}", e);
+    }
+}
+
+// Calls `f` with the time and the text of the lines of the source, oldest first, from the newest
+// rotated file and the current one
+pub fn scan(source: &str, f: impl FnMut(i64, &str)) -> Result<(), String> {
+    match LOGGER.get() {
+        Some(logger) => {
+            let dir = logger.lock().unwrap().dir.clone();
+            scan_dir(&dir, source, f)
+        }
+        None => Ok(()),
+    }
+}
+
+fn scan_dir(dir: &Path, source: &str, mut f: impl FnMut(i64, &str)) -> Result<(), String> {
+    let mut rotated = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| {
+            is_rotated(name) || name.starts_with(ROTATED_PREFIX) && name.ends_with(".log")
+        })
+        .map(|name| name.trim_end_matches(".gz").to_owned())
+        .collect::<Vec<_>>();
+
+    rotated.sort();
+
+    let paths = rotated
+        .last()
+        .map(|name| dir.join(name))
+        .into_iter()
+        .chain([dir.join(CURRENT)]);
+
+    for path in paths {
+        // A rotated file is read as it is until it has been compressed
+        let reader: Box<dyn BufRead> = match open(&path) {
+            Ok(Some(reader)) => reader,
+            Ok(None) => continue,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        for line in reader.lines() {
+            let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+            let time = line.get(..19).and_then(parse_timestamp);
+            let text = line
+                .get(19..)
+                .and_then(|rest| rest.strip_prefix(" ["))
+                .and_then(|rest| rest.strip_prefix(source))
+                .and_then(|rest| rest.strip_prefix("] "));
+
+            if let (Some(time), Some(text)) = (time, text) {
+                f(time, text);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Opens the file, or its compressed copy if it has been compressed, or `None` if neither exists
+fn open(path: &Path) -> io::Result<Option<Box<dyn BufRead>>> {
+    match File::open(path) {
+        Ok(file) => return Ok(Some(Box::new(BufReader::new(file)))),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => (),
+    }
+
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+
+    match File::open(&compressed) {
+        Ok(file) => Ok(Some(Box::new(BufReader::new(GzDecoder::new(file))))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The log files from the newest, starting with the one being written to
+pub fn list() -> Result<Vec<LogFile>, String> {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return Ok(Vec::new()),
+    };
+
+    let dir = logger.lock().unwrap().dir.clone();
+
+    let mut files = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+
+            if name != CURRENT && !is_rotated(&name) {
+                return None;
+            }
+
+            Some(LogFile {
+                name,
+                size: entry.metadata().ok()?.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Rotated files are named after their time, so they sort from the oldest
+    files.sort_by(|a, b| (b.name == CURRENT, &b.name).cmp(&(a.name == CURRENT, &a.name)));
+
+    Ok(files)
+}
+
+pub fn read(name: &str) -> Result<Vec<u8>, String> {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return Err(String::from("Log files are not enabled")),
+    };
+
+    // Only the log files can be read, not any path
+    if name != CURRENT && !is_rotated(name) || name.contains(['/', '\\']) {
+        return Err(format!("No such log file: {}", name));
+    }
+
+    let path = logger.lock().unwrap().dir.join(name);
+    fs::read(&path).map_err(|e| format!("Failed to read {}: {}", name, e))
+}
+
+impl Logger {
+    fn write(&mut self, now: i64, line: &str) -> io::Result<()> {
+        let is_full = self.size > 0 && self.size + line.len() as u64 > self.max_size;
+
+        if is_full || now.div_euclid(DAY) != self.started_at.div_euclid(DAY) {
+            self.rotate(now)?;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.dir.join(CURRENT))?;
+                self.size = file.metadata()?.len();
+                self.file.insert(file)
+            }
+        };
+
+        file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    // Moves the current file aside and compresses it in the background
+    fn rotate(&mut self, now: i64) -> io::Result<()> {
+        self.file = None;
+        self.size = 0;
+
+        let current = self.dir.join(CURRENT);
+        let started_at = self.started_at;
+        self.started_at = now;
+
+        if !current.exists() {
+            return Ok(());
+        }
+
+        // Files rotated within the same second are numbered, zero-padded so they still sort in
+        // time order. The last rotated file may not have been compressed yet
+        let name = (0..)
+            .map(|number| {
+                format!(
+                    "{}{}-{:03}",
+                    ROTATED_PREFIX,
+                    file_timestamp(started_at),
+                    number
+                )
+            })
+            .find(|name| {
+                !self.dir.join(format!("{}.log", name)).exists()
+                    && !self
+                        .dir
+                        .join(format!("{}{}", name, ROTATED_SUFFIX))
+                        .exists()
+            })
+            .unwrap_or_default();
+
+        let rotated = self.dir.join(format!("{}.log", name));
+        fs::rename(&current, &rotated)?;
+
+        let dir = self.dir.clone();
+        let retention = self.retention;
+
+        self.compressing = Some(thread::spawn(move || {
+            if let Err(e) = compress(&rotated) {
+                eprintln!("Failed to compress {}: {}", rotated.display(), e);
+            }
+
+            if let Err(e) = prune(&dir, retention) {
+                eprintln!("Failed to remove old log files: {}", e);
+            }
+        }));
+
+        Ok(())
+    }
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+
+    let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+
+    fs::remove_file(path)
+}
+
+// Removes the oldest rotated files beyond the retention count
+fn prune(dir: &Path, retention: usize) -> io::Result<()> {
+    let mut rotated = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_rotated(name))
+        .collect::<Vec<_>>();
+
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(retention);
+
+    for name in &rotated[..excess] {
+        fs::remove_file(dir.join(name))?;
+    }
+
+    Ok(())
+}
+
+fn is_rotated(name: &str) -> bool {
+    name.starts_with(ROTATED_PREFIX) && name.ends_with(ROTATED_SUFFIX)
+}
+
+// `2021-09-30 12:34:56` in UTC
+fn timestamp(time: i64) -> String {
+    let (year, month, day) = civil_date(time.div_euclid(DAY));
+    let seconds = time.rem_euclid(DAY);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// The Unix time of a `timestamp`
+fn parse_timestamp(text: &str) -> Option<i64> {
+    let number = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+
+    let days = days_from_civil(number(0..4)?, number(5..7)?, number(8..10)?);
+    let seconds = number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+
+    Some(days * DAY + seconds)
+}
+
+// `2021-09-30-123456`, which sorts in time order and is allowed in any file system
+fn file_timestamp(time: i64) -> String {
+    timestamp(time).replace(' ', "-").replace(':', "")
+}
+
+// The year, month and day of the days since 1970-01-01
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// The days since 1970-01-01 of the date, the inverse of `civil_date`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(timestamp(1_633_005_296), "2021-09-30 12:34:56");
+        assert_eq!(file_timestamp(1_633_005_296), "2021-09-30-123456");
+
+        for time in [0, 951_782_400, 1_633_005_296, 4_107_542_399] {
+            assert_eq!(parse_timestamp(&timestamp(time)), Some(time));
+        }
+        assert_eq!(parse_timestamp("2021-09-30"), None);
+    }
+
+    #[test]
+    fn reads_back_lines() {
+        let dir = std::env::temp_dir().join(format!("teledustry-scan-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let rotated = dir.join("teledustry-1970-01-01-000000-000.log");
+        fs::write(
+            &rotated,
+            "1970-01-01 00:00:01 [server] Map loaded.\n1970-01-01 00:00:02 [teledustry] Started\n",
+        )
+        .unwrap();
+        compress(&rotated).unwrap();
+        fs::write(
+            dir.join(CURRENT),
+            "1970-01-02 00:00:00 [server] Anuke: hi\n",
+        )
+        .unwrap();
+
+        let mut lines = Vec::new();
+        scan_dir(&dir, "server", |time, text| {
+            lines.push((time, text.to_owned()))
+        })
+        .unwrap();
+
+        assert_eq!(
+            lines,
+            [
+                (1, String::from("Map loaded.")),
+                (DAY, String::from("Anuke: hi"))
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap_or(());
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("teledustry-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut logger = Logger {
+            dir: dir.clone(),
+            max_size: 16,
+            retention: 10,
+            file: None,
+            size: 0,
+            started_at: 0,
+            compressing: None,
+        };
+
+        logger.write(0, "0123456789\n").unwrap();
+        logger.write(0, "0123456789\n").unwrap();
+        logger.compressing.take().unwrap().join().unwrap();
+        // A file rotated again within the same second gets the next number
+        logger.write(0, "0123456789\n").unwrap();
+        logger.compressing.take().unwrap().join().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join(CURRENT)).unwrap(),
+            "0123456789\n"
+        );
+        assert!(dir.join("teledustry-1970-01-01-000000-000.log.gz").exists());
+        assert!(dir.join("teledustry-1970-01-01-000000-001.log.gz").exists());
+        assert!(!dir.join("teledustry-1970-01-01-000000-000.log").exists());
+
+        // The file rotated last is the newest, and is the one kept
+        prune(&dir, 1).unwrap();
+        assert!(!dir.join("teledustry-1970-01-01-000000-000.log.gz").exists());
+        assert!(dir.join("teledustry-1970-01-01-000000-001.log.gz").exists());
+
+        fs::remove_dir_all(&dir).unwrap_or(());
+    }
+}
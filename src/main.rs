@@ -2,10 +2,51 @@ use crate::{bot::BotInstance, config::Config, game::Game};
 use std::env;
 use tokio::sync::mpsc;
 
+// Prints a diagnostic to stderr and writes it to the log files
+macro_rules! log {
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        eprintln!("{}", text);
+        $crate::logfile::write("teledustry", &text);
+    }};
+}
+
+// Like `log!`, for messages that are not errors, which are printed to stdout
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        println!("{}", text);
+        $crate::logfile::write("teledustry", &text);
+    }};
+}
+
 mod bot;
+mod bridge;
+mod callback;
+mod chunk;
+mod collapse;
 mod command;
 mod config;
+mod crash;
+mod delivery;
+mod duration;
+mod event;
+mod format;
 mod game;
+mod history;
+mod idle;
+mod link;
+mod logfile;
+mod parse;
+mod playlist;
+mod rules;
+mod scrollback;
+mod status;
+mod store;
+mod tempban;
+mod trigger;
+mod vote;
+mod watchdog;
 
 #[tokio::main]
 async fn main() {
@@ -14,39 +55,60 @@ async fn main() {
     let config = match Config::parse(args) {
         Ok(config) => config,
         Err(e) => {
-            eprintln!("{}", e);
+            log!("{}", e);
             return;
         }
     };
 
+    if let Err(e) = logfile::init(&config.log_dir, config.log_size, config.log_retention) {
+        log!("{}", e);
+        return;
+    }
+
     let (output_sender, output_receiver) = mpsc::channel(2);
     let (input_sender, input_receiver) = mpsc::channel(2);
 
     let bot_output_handler = match BotInstance::init(&config) {
         Ok(b) => b,
         Err(e) => {
-            eprintln!("{}", e);
+            log!("{}", e);
             return;
         }
     };
 
     let bot_input_handler = bot_output_handler.clone();
+    let bot_status_handler = bot_output_handler.clone();
+    let bot_tempban_handler = bot_output_handler.clone();
+    let bot_idle_handler = bot_output_handler.clone();
 
-    let handle_bot_output = bot_output_handler.handle_output(output_receiver).await;
+    let handle_bot_output = bot_output_handler
+        .handle_output(output_receiver, input_sender.clone())
+        .await;
+    let handle_bot_status = bot_status_handler.handle_status(input_sender.clone()).await;
+    let handle_bot_tempbans = bot_tempban_handler
+        .handle_tempbans(input_sender.clone())
+        .await;
+    let handle_bot_idle = bot_idle_handler.handle_idle(input_sender.clone()).await;
     let handle_bot_input = bot_input_handler.handle_input(input_sender).await;
 
     match Game::spawn(&config, output_sender, input_receiver).await {
         Ok(_) => (),
         Err(e) => {
-            eprintln!("{}", e);
+            log!("{}", e);
             return;
         }
     }
 
-    match tokio::try_join!(handle_bot_output, handle_bot_input) {
+    match tokio::try_join!(
+        handle_bot_output,
+        handle_bot_input,
+        handle_bot_status,
+        handle_bot_tempbans,
+        handle_bot_idle
+    ) {
         Ok(_) => (),
         Err(e) => {
-            eprintln!("{}", e);
+            log!("{}", e);
             return;
         }
     }
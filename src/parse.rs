@@ -0,0 +1,317 @@
+// Parsers for the output of the game server's console commands
+
+// Parses the output of `maps` into the names accepted by `host`
+pub fn maps(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| line.trim() != "Maps:")
+        .skip(1)
+        .take_while(|line| line.starts_with("  "))
+        .filter_map(|line| {
+            let (name, _) = line.trim().split_once(": ")?;
+            // Custom maps may be listed with their file name, e.g. `Name (name.msav)`
+            let name = name.split(" (").next().unwrap_or(name);
+            Some(name.to_owned())
+        })
+        .collect()
+}
+
+// The named colours Mindustry accepts in `[name]` tags
+const COLORS: [&str; 36] = [
+    "clear",
+    "black",
+    "white",
+    "lightgray",
+    "gray",
+    "darkgray",
+    "blue",
+    "navy",
+    "royal",
+    "slate",
+    "sky",
+    "cyan",
+    "teal",
+    "green",
+    "acid",
+    "lime",
+    "forest",
+    "olive",
+    "yellow",
+    "gold",
+    "goldenrod",
+    "orange",
+    "brown",
+    "tan",
+    "brick",
+    "red",
+    "scarlet",
+    "crimson",
+    "coral",
+    "salmon",
+    "pink",
+    "magenta",
+    "purple",
+    "violet",
+    "maroon",
+    "accent",
+];
+
+// Removes Mindustry colour tags like `[scarlet]`, `[#ff0000]` and `[]`, keeping any other text in
+// square brackets. `[[` is an escaped `[`
+pub fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("[[") {
+            stripped.push('[');
+            rest = after;
+            continue;
+        }
+
+        let tag = rest[1..].split_once(']').map(|(tag, _)| tag);
+
+        match tag {
+            Some(tag) if is_color(tag) => rest = &rest[tag.len() + 2..],
+            _ => {
+                stripped.push('[');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+fn is_color(tag: &str) -> bool {
+    match tag.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => tag.is_empty() || COLORS.contains(&tag.to_ascii_lowercase().as_str()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Player {
+    pub name: String,
+    pub uuid: String,
+    pub ip: String,
+    pub admin: bool,
+}
+
+// Parses the output of `players`, where each player is listed as `[A] name / ID: uuid / IP: ip`,
+// with `[P]` instead of `[A]` for a player who is not an admin
+pub fn players(output: &str) -> Vec<Player> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (admin, line) = match line.get(..4) {
+                Some("[A] ") => (true, &line[4..]),
+                Some("[P] ") => (false, &line[4..]),
+                _ => return None,
+            };
+
+            let (line, ip) = line.rsplit_once(" / IP: ")?;
+            let (name, uuid) = line.rsplit_once(" / ID: ")?;
+
+            Some(Player {
+                name: name.to_owned(),
+                uuid: uuid.to_owned(),
+                ip: ip.to_owned(),
+                admin,
+            })
+        })
+        .collect()
+}
+
+// UUIDs of players are 16 bytes encoded in Base64
+pub fn is_uuid(text: &str) -> bool {
+    text.len() == 24
+        && text.ends_with("==")
+        && text[..22]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+}
+
+// Parses the output of `shufflemode` without arguments, e.g. `Shuffle mode current set to 'all'.`
+pub fn shuffle_mode(output: &str) -> Option<String> {
+    let (_, rest) = output.split_once('\'')?;
+    let (mode, _) = rest.split_once('\'')?;
+
+    Some(mode.to_owned()).filter(|mode| !mode.is_empty())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    // No map is being hosted
+    Closed,
+    Playing(GameStatus),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GameStatus {
+    pub map: String,
+    pub wave: u32,
+    // Seconds until the next wave, if waves are enabled
+    pub next_wave: Option<u32>,
+    pub tps: u32,
+    // In MB
+    pub memory: u32,
+    pub players: Vec<String>,
+}
+
+// Parses the output of `status`
+pub fn status(output: &str) -> Option<Status> {
+    let mut lines = output.lines().map(str::trim);
+
+    match lines.next()? {
+        "Status:" => (),
+        line if line.starts_with("Status:") && line.ends_with("server closed") => {
+            return Some(Status::Closed)
+        }
+        _ => return None,
+    }
+
+    let mut status = GameStatus::default();
+
+    for line in lines {
+        if let Some(line) = line.strip_prefix("Playing on map ") {
+            let (map, wave) = line.rsplit_once(" / Wave ")?;
+            status.map = map.to_owned();
+            status.wave = wave.parse().ok()?;
+        } else if let Some(seconds) = line.strip_suffix(" seconds until next wave.") {
+            status.next_wave = seconds.parse().ok();
+        } else if let Some((tps, memory)) = line.split_once(" FPS, ") {
+            status.tps = tps.parse().ok()?;
+            status.memory = memory.strip_suffix(" MB used.")?.parse().ok()?;
+        } else if line.starts_with("Players: ") || line == "No players connected." {
+            continue;
+        } else if let Some((name, _uuid)) = line.rsplit_once(" / ") {
+            // Player names may contain ` / ` themselves, but UUIDs never do
+            if !line.ends_with(" enemies") {
+                status.players.push(strip_colors(name));
+            }
+        }
+    }
+
+    if status.map.is_empty() {
+        None
+    } else {
+        Some(Status::Playing(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_maps() {
+        let output = "Maps:\n  Ancient_Caldera: Default / 256x256\n  Frozen_Forest: Default / 150x150\n  My_Map (my_map.msav): Custom / 100x100\nMap directory: /srv/mindustry/config/maps\n";
+        assert_eq!(
+            maps(output),
+            vec!["Ancient_Caldera", "Frozen_Forest", "My_Map"]
+        );
+    }
+
+    #[test]
+    fn parses_no_maps() {
+        assert!(maps("No maps found.\nMap directory: /srv/mindustry/config/maps\n").is_empty());
+    }
+
+    #[test]
+    fn parses_status() {
+        let output = "Status:\n  Playing on map Frozen_Forest / Wave 12\n  85 seconds until next wave.\n  34 units / 5 enemies\n  60 FPS, 214 MB used.\n  Players: 2\n    Anuke / 6PBz8jUBWqgAAAAAjkP5Ug==\n    a / b / RnF4Yw7C3bEAAAAAmCzCvg==\n";
+        assert_eq!(
+            status(output),
+            Some(Status::Playing(GameStatus {
+                map: String::from("Frozen_Forest"),
+                wave: 12,
+                next_wave: Some(85),
+                tps: 60,
+                memory: 214,
+                players: vec![String::from("Anuke"), String::from("a / b")],
+            }))
+        );
+    }
+
+    #[test]
+    fn parses_status_without_waves_or_players() {
+        let output = "Status:\n  Playing on map Ancient_Caldera / Wave 1\n  0 units / 0 enemies\n  59 FPS, 98 MB used.\n  No players connected.\n";
+        assert_eq!(
+            status(output),
+            Some(Status::Playing(GameStatus {
+                map: String::from("Ancient_Caldera"),
+                wave: 1,
+                next_wave: None,
+                tps: 59,
+                memory: 98,
+                players: Vec::new(),
+            }))
+        );
+    }
+
+    #[test]
+    fn parses_closed_status() {
+        assert_eq!(status("Status: server closed\n"), Some(Status::Closed));
+    }
+
+    #[test]
+    fn rejects_other_output() {
+        assert_eq!(status("Commands:\n"), None);
+        assert_eq!(status("Status:\n"), None);
+    }
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(
+            strip_colors("[scarlet]Red[] and [#00ff00ff]green"),
+            "Red and green"
+        );
+        assert_eq!(strip_colors("[TG] name: [[1] [GOLD]x"), "[TG] name: [1] x");
+        assert_eq!(strip_colors("[#12] [unclosed"), "[#12] [unclosed");
+    }
+
+    #[test]
+    fn parses_players() {
+        let output = "Players: 2\n [A] Anuke / ID: 6PBz8jUBWqgAAAAAjkP5Ug== / IP: 127.0.0.1\n [P] a / b / ID: RnF4Yw7C3bEAAAAAmCzCvg== / IP: 10.0.0.2\n";
+        assert_eq!(
+            players(output),
+            vec![
+                Player {
+                    name: String::from("Anuke"),
+                    uuid: String::from("6PBz8jUBWqgAAAAAjkP5Ug=="),
+                    ip: String::from("127.0.0.1"),
+                    admin: true,
+                },
+                Player {
+                    name: String::from("a / b"),
+                    uuid: String::from("RnF4Yw7C3bEAAAAAmCzCvg=="),
+                    ip: String::from("10.0.0.2"),
+                    admin: false,
+                },
+            ]
+        );
+        assert!(players("No players are currently in the server.\n").is_empty());
+    }
+
+    #[test]
+    fn parses_shuffle_mode() {
+        assert_eq!(
+            shuffle_mode("Shuffle mode current set to 'custom'.\n").as_deref(),
+            Some("custom")
+        );
+        assert_eq!(shuffle_mode("Invalid command.\n"), None);
+    }
+
+    #[test]
+    fn recognizes_uuids() {
+        assert!(is_uuid("6PBz8jUBWqgAAAAAjkP5Ug=="));
+        assert!(!is_uuid("Anuke"));
+        assert!(!is_uuid("6PBz8jUBWqgAAAAAjkP5Ug:="));
+    }
+}
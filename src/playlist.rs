@@ -0,0 +1,211 @@
+use crate::{
+    bot::BotUpdateHandler,
+    parse,
+    store::{PlaylistEntry, Store},
+};
+use rand::Rng;
+
+pub const DEFAULT_PLAYLIST: &str = "default";
+
+// The playlist in use, whether it is shuffled and the position of the entry last hosted are kept
+// as settings
+const PLAYLIST_SETTING: &str = "playlist";
+const SHUFFLE_SETTING: &str = "playlist_shuffle";
+const POSITION_SETTING: &str = "playlist_position";
+// The shuffle mode of the server before the playlist turned its map rotation off
+const ROTATION_SETTING: &str = "playlist_server_shufflemode";
+
+pub fn active(store: &Store) -> Result<String, String> {
+    Ok(store
+        .setting(PLAYLIST_SETTING)?
+        .unwrap_or_else(|| String::from(DEFAULT_PLAYLIST)))
+}
+
+// Whether the playlist hosts the maps, instead of the server's own rotation
+pub fn is_in_use(store: &Store) -> Result<bool, String> {
+    Ok(!store.playlist(&active(store)?)?.is_empty())
+}
+
+pub fn set_active(store: &Store, name: &str) -> Result<(), String> {
+    store.set_setting(PLAYLIST_SETTING, name)?;
+    store.set_setting(POSITION_SETTING, "")
+}
+
+pub fn is_shuffled(store: &Store) -> Result<bool, String> {
+    Ok(store.setting(SHUFFLE_SETTING)?.as_deref() == Some("on"))
+}
+
+pub fn set_shuffled(store: &Store, is_shuffled: bool) -> Result<(), String> {
+    store.set_setting(SHUFFLE_SETTING, if is_shuffled { "on" } else { "off" })
+}
+
+// The position of the entry last hosted
+pub fn position(store: &Store) -> Result<Option<usize>, String> {
+    Ok(store
+        .setting(POSITION_SETTING)?
+        .and_then(|position| position.parse().ok()))
+}
+
+pub fn set_position(store: &Store, position: Option<usize>) -> Result<(), String> {
+    let position = position.map(|position| position.to_string());
+    store.set_setting(POSITION_SETTING, position.as_deref().unwrap_or_default())
+}
+
+// The position of the entry last hosted once the entry at `removed` is removed. When it is the one
+// removed, the playlist goes on with the entry that took its place
+pub fn position_after_remove(last: usize, removed: usize) -> Option<usize> {
+    if last >= removed {
+        last.checked_sub(1)
+    } else {
+        Some(last)
+    }
+}
+
+// The position of the entry last hosted once an entry is moved, so it still points at that entry
+pub fn position_after_move(last: usize, from: usize, to: usize) -> usize {
+    if last == from {
+        to
+    } else if from < last && last <= to {
+        last - 1
+    } else if to <= last && last < from {
+        last + 1
+    } else {
+        last
+    }
+}
+
+// Hosts the map queued by a vote, or else the next entry of the playlist in use, stopping the game
+// in progress first if needed. Returns the hosted entry, or `None` if there is nothing to host
+pub async fn host_next(
+    handler: &BotUpdateHandler,
+    is_hosted: bool,
+) -> Result<Option<PlaylistEntry>, String> {
+    let queued = handler.context.next_map.lock().await.take();
+
+    let entry = match queued {
+        Some(entry) => entry,
+        None => {
+            let store = &handler.context.store;
+
+            let entries = store.playlist(&active(store)?)?;
+
+            if entries.is_empty() {
+                return Ok(None);
+            }
+
+            let position = next_position(position(store)?, entries.len(), is_shuffled(store)?);
+            store.set_setting(POSITION_SETTING, &position.to_string())?;
+
+            // The server would otherwise load a map of its own after a game over, in place of
+            // the next one of the playlist
+            disable_rotation(handler).await?;
+
+            entries[position].clone()
+        }
+    };
+
+    if is_hosted {
+        handler.send_command(String::from("stop")).await;
+    }
+
+    handler
+        .send_command(format!("host {} {}", entry.map, entry.mode))
+        .await;
+
+    Ok(Some(entry))
+}
+
+async fn disable_rotation(handler: &BotUpdateHandler) -> Result<(), String> {
+    let store = &handler.context.store;
+
+    // The mode is only kept the first time, as the server has none afterwards
+    if store
+        .setting(ROTATION_SETTING)?
+        .unwrap_or_default()
+        .is_empty()
+    {
+        let mode = handler
+            .query(String::from("shufflemode"))
+            .await
+            .and_then(|output| parse::shuffle_mode(&output))
+            // The default of the server
+            .unwrap_or_else(|| String::from("all"));
+
+        store.set_setting(ROTATION_SETTING, &mode)?;
+    }
+
+    handler.send_command(String::from("shufflemode none")).await;
+
+    Ok(())
+}
+
+// Turns the server's own map rotation back on once the playlist is not in use anymore
+pub async fn restore_rotation(handler: &BotUpdateHandler) -> Result<(), String> {
+    let store = &handler.context.store;
+
+    if is_in_use(store)? {
+        return Ok(());
+    }
+
+    let mode = store.setting(ROTATION_SETTING)?.unwrap_or_default();
+
+    if !mode.is_empty() {
+        handler.send_command(format!("shufflemode {}", mode)).await;
+        store.set_setting(ROTATION_SETTING, "")?;
+    }
+
+    Ok(())
+}
+
+fn next_position(last: Option<usize>, len: usize, is_shuffled: bool) -> usize {
+    match last {
+        // The entry last hosted is not picked again, unless it is the only one
+        Some(last) if is_shuffled && len > 1 => {
+            let position = rand::thread_rng().gen_range(0..len - 1);
+            if position >= last.min(len - 1) {
+                position + 1
+            } else {
+                position
+            }
+        }
+        _ if is_shuffled => rand::thread_rng().gen_range(0..len),
+        Some(last) => (last + 1) % len,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_in_order() {
+        assert_eq!(next_position(None, 3, false), 0);
+        assert_eq!(next_position(Some(0), 3, false), 1);
+        assert_eq!(next_position(Some(2), 3, false), 0);
+        // The playlist may have been shortened since
+        assert_eq!(next_position(Some(5), 3, false), 0);
+    }
+
+    #[test]
+    fn keeps_position_on_edits() {
+        assert_eq!(position_after_remove(2, 0), Some(1));
+        assert_eq!(position_after_remove(2, 2), Some(1));
+        assert_eq!(position_after_remove(0, 0), None);
+        assert_eq!(position_after_remove(1, 2), Some(1));
+
+        assert_eq!(position_after_move(1, 1, 3), 3);
+        assert_eq!(position_after_move(2, 0, 3), 1);
+        assert_eq!(position_after_move(2, 3, 0), 3);
+        assert_eq!(position_after_move(2, 3, 4), 2);
+    }
+
+    #[test]
+    fn shuffles_without_repeating() {
+        for _ in 0..100 {
+            assert_ne!(next_position(Some(1), 3, true), 1);
+            assert!(next_position(Some(1), 3, true) < 3);
+        }
+        assert_eq!(next_position(Some(0), 1, true), 0);
+    }
+}
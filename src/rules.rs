@@ -0,0 +1,160 @@
+use crate::store::Store;
+use regex::Regex;
+use std::{fmt, str::FromStr};
+
+const DEFAULT_EMOJI: &str = "🔔";
+
+// What to do with a line of the output that matches a rule
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    // Not sent to any chat
+    Drop,
+    // Sent only to these chats
+    Forward(Vec<i64>),
+    // Sent as a new message with the emoji in front, mentioning the admin
+    Highlight(String),
+    // Also sent to the alert chats as a new message, which notifies
+    Alert,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+
+        match (name, arg) {
+            ("drop", None) => Ok(Self::Drop),
+            ("forward", Some(chats)) => chats
+                .split(',')
+                .map(|chat_id| {
+                    chat_id
+                        .parse()
+                        .map_err(|_| format!("Invalid chat id: {}", chat_id))
+                })
+                .collect::<Result<_, _>>()
+                .map(Self::Forward),
+            ("highlight", None) => Ok(Self::Highlight(String::from(DEFAULT_EMOJI))),
+            ("highlight", Some(emoji)) if !emoji.is_empty() => {
+                Ok(Self::Highlight(emoji.to_owned()))
+            }
+            ("alert", None) => Ok(Self::Alert),
+            _ => Err(format!("Unknown rule action: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Drop => write!(f, "drop"),
+            Self::Forward(chats) => {
+                let chats = chats
+                    .iter()
+                    .map(|chat_id| chat_id.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "forward:{}", chats.join(","))
+            }
+            Self::Highlight(emoji) => write!(f, "highlight:{}", emoji),
+            Self::Alert => write!(f, "alert"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Rule {
+    // The id in the store, or `None` for the rules given on the command line
+    pub id: Option<i64>,
+    pub action: Action,
+    pub regex: Regex,
+}
+
+impl Rule {
+    pub fn new(id: Option<i64>, action: &str, pattern: &str) -> Result<Self, String> {
+        Ok(Self {
+            id,
+            action: action.parse()?,
+            regex: Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))?,
+        })
+    }
+}
+
+// Rules are written as `<action> <regex>`, e.g. `drop ^Saved`
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (action, pattern) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("A rule needs an action and a regex: {}", s))?;
+
+        Self::new(None, action, pattern.trim_start())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.action, self.regex)
+    }
+}
+
+// The rules given on the command line, followed by the ones added with `/rules`
+pub fn load(config_rules: &[Rule], store: &Store) -> Result<Vec<Rule>, String> {
+    let mut rules = config_rules.to_vec();
+
+    for stored in store.rules()? {
+        match Rule::new(Some(stored.id), &stored.action, &stored.pattern) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => log!("Ignoring the output rule {}: {}", stored.id, e),
+        }
+    }
+
+    Ok(rules)
+}
+
+// The first rule the line matches
+pub fn find<'a>(rules: &'a [Rule], line: &str) -> Option<&'a Rule> {
+    rules.iter().find(|rule| rule.regex.is_match(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rules() {
+        let rule = "forward:-1001,42 has connected".parse::<Rule>().unwrap();
+        assert_eq!(rule.action, Action::Forward(vec![-1001, 42]));
+        assert_eq!(rule.to_string(), "forward:-1001,42 has connected");
+
+        let rule = "highlight Game over!".parse::<Rule>().unwrap();
+        assert_eq!(rule.action, Action::Highlight(String::from(DEFAULT_EMOJI)));
+
+        assert!("drop".parse::<Rule>().is_err());
+        assert!("mute ^Saved".parse::<Rule>().is_err());
+        assert!("alert (".parse::<Rule>().is_err());
+        assert!("forward:x ^Saved".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn applies_the_first_matching_rule() {
+        let rules = ["drop ^Saved", "alert Exception", "highlight Saved"]
+            .iter()
+            .map(|rule| rule.parse::<Rule>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            find(&rules, "Saved to slot 1.").map(|rule| &rule.action),
+            Some(&Action::Drop)
+        );
+        assert_eq!(
+            find(&rules, "java.lang.NullPointerException").map(|rule| &rule.action),
+            Some(&Action::Alert)
+        );
+        assert!(find(&rules, "Map loaded.").is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{logfile, store};
+use regex::Regex;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::task;
+
+// The lines are logged with this source, which is where the older lines are read back from
+const SOURCE: &str = "server";
+
+#[derive(Clone)]
+pub struct Line {
+    // Unix time the line was output at
+    pub time: i64,
+    pub text: String,
+}
+
+// The recent lines of the output. The lines are also written to the log files, where the lines that
+// no longer fit in memory are searched
+#[derive(Clone)]
+pub struct Scrollback {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    lines: VecDeque<Line>,
+    capacity: usize,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                lines: VecDeque::with_capacity(capacity),
+                capacity,
+            })),
+        }
+    }
+
+    pub fn push(&self, text: &str) {
+        let line = Line {
+            time: store::now(),
+            text: text.trim_end_matches('\n').to_owned(),
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+
+        // Logged under the lock, so the log files have the lines in the same order
+        logfile::write_at(line.time, SOURCE, &line.text);
+
+        inner.lines.push_back(line);
+
+        if inner.lines.len() > inner.capacity {
+            inner.lines.pop_front();
+        }
+    }
+
+    pub async fn tail(&self, count: usize) -> Result<Vec<Line>, String> {
+        self.last(count, |_| true).await
+    }
+
+    pub async fn grep(&self, regex: Regex, count: usize) -> Result<Vec<Line>, String> {
+        self.last(count, move |line| regex.is_match(&line.text))
+            .await
+    }
+
+    pub async fn since(&self, time: i64) -> Result<Vec<Line>, String> {
+        self.last(usize::MAX, move |line| line.time >= time).await
+    }
+
+    // The last lines that pass the filter, oldest first
+    async fn last(
+        &self,
+        count: usize,
+        filter: impl Fn(&Line) -> bool + Send + 'static,
+    ) -> Result<Vec<Line>, String> {
+        // The lines in memory are copied, so the output is not held up while the files are read
+        let lines = self.inner.lock().unwrap().lines.iter().cloned().collect();
+
+        task::spawn_blocking(move || search(lines, count, filter, |f| logfile::scan(SOURCE, f)))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+}
+
+// Searches the logged lines followed by the lines in memory, which are the last ones logged
+fn search(
+    lines: Vec<Line>,
+    count: usize,
+    filter: impl Fn(&Line) -> bool,
+    scan: impl FnOnce(&mut dyn FnMut(i64, &str)) -> Result<(), String>,
+) -> Result<Vec<Line>, String> {
+    let mut found = VecDeque::new();
+
+    let mut keep = |line: Line| {
+        if filter(&line) {
+            if found.len() == count {
+                found.pop_front();
+            }
+            found.push_back(line);
+        }
+    };
+
+    // The logged lines up to the second of the oldest line in memory are read, leaving out the
+    // last ones of that second, which are the lines in memory themselves
+    let oldest = lines.first().map(|line| line.time).unwrap_or(i64::MAX);
+    let copies = lines.iter().take_while(|line| line.time == oldest).count();
+    let mut held = VecDeque::new();
+
+    scan(&mut |time, text| {
+        if time > oldest {
+            return;
+        }
+
+        held.push_back(Line {
+            time,
+            text: text.to_owned(),
+        });
+
+        if held.len() > copies {
+            if let Some(line) = held.pop_front() {
+                keep(line);
+            }
+        }
+    })?;
+
+    for line in held {
+        if line.time < oldest {
+            keep(line);
+        }
+    }
+
+    for line in lines {
+        keep(line);
+    }
+
+    Ok(found.into())
+}
+
+// Renders the lines with the UTC time of day they were output at
+pub fn render(lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            let seconds = line.time.rem_euclid(24 * 60 * 60);
+            format!(
+                "{:02}:{:02}:{:02} {}\n",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                line.text
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_logged_lines() {
+        let line = |time, text: &str| Line {
+            time,
+            text: text.to_owned(),
+        };
+
+        let logged = [
+            line(1, "Map loaded."),
+            line(2, "Anuke has connected."),
+            line(2, "Anuke: hi"),
+            line(2, "Stopped server."),
+        ];
+        // The capacity is 2, so only the last two lines are still in memory
+        let in_memory = || logged[2..].to_vec();
+        let scan = |f: &mut dyn FnMut(i64, &str)| {
+            for line in &logged {
+                f(line.time, &line.text);
+            }
+            Ok(())
+        };
+
+        let texts = |lines: Vec<Line>| lines.into_iter().map(|line| line.text).collect::<Vec<_>>();
+
+        assert_eq!(
+            texts(search(in_memory(), 3, |_| true, scan).unwrap()),
+            ["Anuke has connected.", "Anuke: hi", "Stopped server."]
+        );
+
+        let regex = Regex::new("Anuke").unwrap();
+        assert_eq!(
+            texts(search(in_memory(), 10, |line| regex.is_match(&line.text), scan).unwrap()),
+            ["Anuke has connected.", "Anuke: hi"]
+        );
+        assert_eq!(
+            search(in_memory(), usize::MAX, |_| true, scan)
+                .unwrap()
+                .len(),
+            4
+        );
+        assert!(search(in_memory(), usize::MAX, |line| line.time > 2, scan)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn renders_time_of_day() {
+        let lines = [Line {
+            time: 3661,
+            text: String::from("Map loaded."),
+        }];
+
+        assert_eq!(render(&lines), "01:01:01 Map loaded.\n");
+    }
+}
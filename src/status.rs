@@ -0,0 +1,112 @@
+use crate::{
+    bot::BotUpdateHandler,
+    parse::{self, Status},
+};
+use std::{collections::HashMap, time::Duration};
+use tgbot::{
+    methods::{EditMessageText, PinChatMessage, SendMessage},
+    types::Integer,
+    ExecuteError,
+};
+use tokio::time;
+
+// Keeps a status message pinned in each of the chats, refreshing it by running `status`
+pub async fn run(handler: BotUpdateHandler, chats: Vec<i64>, interval: Duration) {
+    let mut messages = HashMap::new();
+    let mut interval = time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let output = handler.query(String::from("status")).await;
+        let mode = handler.context.mode.lock().await.clone();
+        let text = render(output.as_deref(), mode);
+
+        for chat_id in &chats {
+            match update(&handler, *chat_id, messages.get(chat_id).copied(), &text).await {
+                Ok(message_id) => {
+                    messages.insert(*chat_id, message_id);
+                }
+                Err(e) => {
+                    log!("Failed to update the status in chat {}: {}", chat_id, e);
+                    // The message may have been deleted, so a new one is sent next time
+                    messages.remove(chat_id);
+                }
+            }
+        }
+    }
+}
+
+async fn update(
+    handler: &BotUpdateHandler,
+    chat_id: i64,
+    message_id: Option<Integer>,
+    text: &str,
+) -> Result<Integer, ExecuteError> {
+    match message_id {
+        Some(message_id) => {
+            let edit_message_text = EditMessageText::new(chat_id, message_id, text);
+
+            match handler.api.execute(edit_message_text).await {
+                Ok(_) => Ok(message_id),
+                Err(ExecuteError::Response(e))
+                    if e.description().contains("message is not modified") =>
+                {
+                    Ok(message_id)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        None => {
+            let message = handler.api.execute(SendMessage::new(chat_id, text)).await?;
+
+            // The message is still kept up to date if the bot may not pin it
+            let pin_chat_message =
+                PinChatMessage::new(chat_id, message.id).disable_notification(true);
+            if let Err(e) = handler.api.execute(pin_chat_message).await {
+                log!("Failed to pin the status in chat {}: {}", chat_id, e);
+            }
+
+            Ok(message.id)
+        }
+    }
+}
+
+// `output` is the response to `status`, or `None` if the server did not respond
+fn render(output: Option<&str>, mode: Option<String>) -> String {
+    let output = match output {
+        Some(output) => output,
+        None => return String::from("🔴 Offline"),
+    };
+
+    let status = match parse::status(output) {
+        Some(Status::Playing(status)) => status,
+        Some(Status::Closed) => return String::from("🟡 Online, no map hosted"),
+        None => return String::from("🟢 Online, the status could not be read"),
+    };
+
+    let mut text = format!("🟢 Online\nMap: {}", status.map);
+
+    if let Some(mode) = mode {
+        text.push_str(&format!(" ({})", mode));
+    }
+
+    text.push_str(&format!("\nWave: {}", status.wave));
+
+    if let Some(next_wave) = status.next_wave {
+        text.push_str(&format!(" (next in {}s)", next_wave));
+    }
+
+    text.push_str(&format!(
+        "\nTPS: {}\nMemory: {} MB\nPlayers: {}",
+        status.tps,
+        status.memory,
+        status.players.len()
+    ));
+
+    for player in &status.players {
+        text.push_str(&format!("\n  {}", player));
+    }
+
+    text
+}
@@ -0,0 +1,1074 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tempbans (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL,
+    name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    banned_by TEXT NOT NULL,
+    banned_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    lifted INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chat_formats (
+    chat_id INTEGER PRIMARY KEY,
+    format TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS join_requests (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL,
+    name TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    chat_id INTEGER NOT NULL,
+    requested_at INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    decided_by TEXT,
+    decided_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS links (
+    user_id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    linked_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS players (
+    uuid TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    playtime INTEGER NOT NULL DEFAULT 0,
+    sessions INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL,
+    name TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    left_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS matches (
+    id INTEGER PRIMARY KEY,
+    map TEXT,
+    mode TEXT,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    waves INTEGER,
+    winner TEXT,
+    peak_players INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS playlists (
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    map TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    PRIMARY KEY (name, position)
+);
+
+CREATE TABLE IF NOT EXISTS rules (
+    id INTEGER PRIMARY KEY,
+    action TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS triggers (
+    id INTEGER PRIMARY KEY,
+    pattern TEXT NOT NULL,
+    command TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL
+);
+";
+
+// The playtime includes the session in progress
+const PLAYER_STATS: &str = "
+SELECT uuid, name, first_seen, last_seen, sessions, playtime + COALESCE((
+    SELECT SUM(?1 - joined_at) FROM sessions
+    WHERE sessions.uuid = players.uuid AND left_at IS NULL
+), 0) AS total
+FROM players
+";
+
+pub struct TempBan {
+    pub id: i64,
+    pub uuid: String,
+    pub name: String,
+    pub reason: String,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
+
+pub struct JoinRequest {
+    pub id: i64,
+    pub uuid: String,
+    pub name: String,
+    pub user_id: i64,
+    // The Telegram user who sent the request
+    pub username: String,
+    // The chat the request was sent from, where the decision is reported
+    pub chat_id: i64,
+}
+
+// A Telegram user bound to a player
+pub struct Link {
+    pub user_id: i64,
+    pub uuid: String,
+    pub name: String,
+}
+
+pub struct PlayerStats {
+    pub uuid: String,
+    pub name: String,
+    // Unix timestamps in seconds
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub sessions: i64,
+    // In seconds
+    pub playtime: i64,
+}
+
+// A match that has not ended has no `ended_at`, which is also the case for the ones that were
+// in progress when teledustry stopped
+pub struct Match {
+    pub id: i64,
+    pub map: Option<String>,
+    pub mode: Option<String>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub waves: Option<i64>,
+    pub winner: Option<String>,
+    pub peak_players: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistEntry {
+    pub map: String,
+    pub mode: String,
+}
+
+// An output rule added with `/rules`, kept as written so it is checked again when loaded
+pub struct StoredRule {
+    pub id: i64,
+    pub action: String,
+    pub pattern: String,
+}
+
+pub struct StoredTrigger {
+    pub id: i64,
+    pub pattern: String,
+    pub command: String,
+}
+
+pub struct AuditEntry {
+    // Unix timestamp in seconds
+    pub time: i64,
+    // The Telegram user, or what acted on its own, e.g. a trigger
+    pub actor: String,
+    pub action: String,
+}
+
+// The persistent state of teledustry, kept in a SQLite database
+#[derive(Clone)]
+pub struct Store {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("Failed to open the database {}: {}", path, e))?;
+
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to initialize the database {}: {}", path, e))?;
+
+        // The game server is started along with teledustry, so no session from before can still
+        // be in progress. When they ended is unknown, so they do not count towards the playtime
+        connection
+            .execute(
+                "UPDATE sessions SET left_at = joined_at WHERE left_at IS NULL",
+                [],
+            )
+            .map_err(|e| format!("Failed to initialize the database {}: {}", path, e))?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub fn add_tempban(&self, ban: &TempBan, banned_by: &str) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO tempbans (uuid, name, reason, banned_by, banned_at, expires_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    ban.uuid,
+                    ban.name,
+                    ban.reason,
+                    banned_by,
+                    now(),
+                    ban.expires_at
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // The temporary bans that have not been lifted yet, the earliest to expire first
+    pub fn tempbans(&self) -> Result<Vec<TempBan>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare(
+                "SELECT id, uuid, name, reason, expires_at FROM tempbans
+                WHERE lifted = 0 ORDER BY expires_at",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let tempbans = statement
+            .query_map([], |row| {
+                Ok(TempBan {
+                    id: row.get(0)?,
+                    uuid: row.get(1)?,
+                    name: row.get(2)?,
+                    reason: row.get(3)?,
+                    expires_at: row.get(4)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(tempbans)
+    }
+
+    pub fn lift_tempban(&self, id: i64) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE tempbans SET lifted = 1 WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn setting(&self, key: &str) -> Result<Option<String>, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn set_chat_format(&self, chat_id: i64, format: &str) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO chat_formats (chat_id, format) VALUES (?1, ?2)",
+                params![chat_id, format],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn chat_formats(&self) -> Result<Vec<(i64, String)>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT chat_id, format FROM chat_formats")
+            .map_err(|e| e.to_string())?;
+
+        let formats = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(formats)
+    }
+
+    pub fn add_join_request(&self, request: &JoinRequest) -> Result<i64, String> {
+        let connection = self.connection.lock().unwrap();
+
+        connection
+            .execute(
+                "INSERT INTO join_requests (uuid, name, user_id, username, chat_id, requested_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    request.uuid,
+                    request.name,
+                    request.user_id,
+                    request.username,
+                    request.chat_id,
+                    now()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    // The time of the last request of the user, in seconds
+    pub fn last_join_request(&self, user_id: i64) -> Result<Option<i64>, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT MAX(requested_at) FROM join_requests WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    // Whether a request to join as the player, by UUID or name, is waiting for a decision
+    pub fn has_pending_join_request(&self, player: &str) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT EXISTS (
+                    SELECT 1 FROM join_requests
+                    WHERE (uuid = ?1 OR name = ?1 COLLATE NOCASE) AND status = 'pending'
+                )",
+                params![player],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    // Records the decision on a pending request and returns the request, or `None` if it has
+    // already been decided
+    pub fn decide_join_request(
+        &self,
+        id: i64,
+        is_approved: bool,
+        decided_by: &str,
+    ) -> Result<Option<JoinRequest>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let status = if is_approved { "approved" } else { "denied" };
+
+        let updated = connection
+            .execute(
+                "UPDATE join_requests SET status = ?1, decided_by = ?2, decided_at = ?3
+                WHERE id = ?4 AND status = 'pending'",
+                params![status, decided_by, now(), id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        connection
+            .query_row(
+                "SELECT id, uuid, name, user_id, username, chat_id FROM join_requests
+                WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(JoinRequest {
+                        id: row.get(0)?,
+                        uuid: row.get(1)?,
+                        name: row.get(2)?,
+                        user_id: row.get(3)?,
+                        username: row.get(4)?,
+                        chat_id: row.get(5)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    // A player is linked to at most one Telegram user, so an older link of either is replaced
+    pub fn add_link(&self, link: &Link) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO links (user_id, uuid, name, linked_at)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![link.user_id, link.uuid, link.name, now()],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn link(&self, user_id: i64) -> Result<Option<Link>, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT user_id, uuid, name FROM links WHERE user_id = ?1",
+                params![user_id],
+                |row| {
+                    Ok(Link {
+                        user_id: row.get(0)?,
+                        uuid: row.get(1)?,
+                        name: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn links(&self) -> Result<Vec<Link>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT user_id, uuid, name FROM links")
+            .map_err(|e| e.to_string())?;
+
+        let links = statement
+            .query_map([], |row| {
+                Ok(Link {
+                    user_id: row.get(0)?,
+                    uuid: row.get(1)?,
+                    name: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(links)
+    }
+
+    // Returns whether the user was linked
+    pub fn remove_link(&self, user_id: i64) -> Result<bool, String> {
+        let removed = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM links WHERE user_id = ?1", params![user_id])
+            .map_err(|e| e.to_string())?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn start_session(&self, uuid: &str, name: &str) -> Result<(), String> {
+        let connection = self.connection.lock().unwrap();
+        let now = now();
+
+        connection
+            .execute(
+                "INSERT INTO players (uuid, name, first_seen, last_seen, sessions)
+                VALUES (?1, ?2, ?3, ?3, 1)
+                ON CONFLICT (uuid) DO UPDATE
+                SET name = excluded.name, last_seen = excluded.last_seen, sessions = sessions + 1",
+                params![uuid, name, now],
+            )
+            .and_then(|_| {
+                connection.execute(
+                    "INSERT INTO sessions (uuid, name, joined_at) VALUES (?1, ?2, ?3)",
+                    params![uuid, name, now],
+                )
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // Without the UUID, the session is found by the name of the player
+    pub fn end_session(&self, uuid: Option<&str>, name: &str) -> Result<(), String> {
+        let connection = self.connection.lock().unwrap();
+        let now = now();
+
+        let session = connection
+            .query_row(
+                "SELECT id, uuid, joined_at FROM sessions
+                WHERE left_at IS NULL AND (uuid = ?1 OR (?1 IS NULL AND name = ?2))
+                ORDER BY joined_at DESC LIMIT 1",
+                params![uuid, name],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+
+        let (id, uuid, joined_at) = match session {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        connection
+            .execute(
+                "UPDATE sessions SET left_at = ?1 WHERE id = ?2",
+                params![now, id],
+            )
+            .and_then(|_| {
+                connection.execute(
+                    "UPDATE players SET last_seen = ?1, playtime = playtime + ?2 WHERE uuid = ?3",
+                    params![now, now - joined_at, uuid],
+                )
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // The player is looked up by UUID, or by the name they were last seen with
+    pub fn player_stats(&self, player: &str) -> Result<Option<PlayerStats>, String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!(
+                    "{} WHERE uuid = ?2 OR name = ?2 COLLATE NOCASE ORDER BY uuid = ?2 DESC, last_seen DESC LIMIT 1",
+                    PLAYER_STATS
+                ),
+                params![now(), player],
+                player_stats_from_row,
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn top_players(&self, limit: usize) -> Result<Vec<PlayerStats>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare(&format!("{} ORDER BY total DESC LIMIT ?2", PLAYER_STATS))
+            .map_err(|e| e.to_string())?;
+
+        let players = statement
+            .query_map(params![now(), limit as i64], player_stats_from_row)
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(players)
+    }
+
+    // Returns the id of the match
+    pub fn start_match(
+        &self,
+        map: Option<&str>,
+        mode: Option<&str>,
+        players: usize,
+    ) -> Result<i64, String> {
+        let connection = self.connection.lock().unwrap();
+
+        connection
+            .execute(
+                "INSERT INTO matches (map, mode, started_at, peak_players) VALUES (?1, ?2, ?3, ?4)",
+                params![map, mode, now(), players as i64],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    pub fn set_match_map(&self, id: i64, map: &str) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE matches SET map = ?1 WHERE id = ?2",
+                params![map, id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn update_match_peak(&self, id: i64, players: usize) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE matches SET peak_players = MAX(peak_players, ?1) WHERE id = ?2",
+                params![players as i64, id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn end_match(
+        &self,
+        id: i64,
+        waves: Option<u32>,
+        winner: Option<&str>,
+    ) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE matches SET ended_at = ?1, waves = ?2, winner = ?3 WHERE id = ?4",
+                params![now(), waves, winner, id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // The most recent matches first, or all of them without a limit
+    pub fn matches(&self, limit: Option<usize>) -> Result<Vec<Match>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare(
+                "SELECT id, map, mode, started_at, ended_at, waves, winner, peak_players
+                FROM matches ORDER BY started_at DESC, id DESC LIMIT ?1",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let limit = limit.map_or(-1, |limit| limit as i64);
+
+        let matches = statement
+            .query_map(params![limit], |row| {
+                Ok(Match {
+                    id: row.get(0)?,
+                    map: row.get(1)?,
+                    mode: row.get(2)?,
+                    started_at: row.get(3)?,
+                    ended_at: row.get(4)?,
+                    waves: row.get(5)?,
+                    winner: row.get(6)?,
+                    peak_players: row.get(7)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(matches)
+    }
+
+    pub fn playlist(&self, name: &str) -> Result<Vec<PlaylistEntry>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT map, mode FROM playlists WHERE name = ?1 ORDER BY position")
+            .map_err(|e| e.to_string())?;
+
+        let entries = statement
+            .query_map(params![name], |row| {
+                Ok(PlaylistEntry {
+                    map: row.get(0)?,
+                    mode: row.get(1)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(entries)
+    }
+
+    // Replaces the entries of the playlist
+    pub fn set_playlist(&self, name: &str, entries: &[PlaylistEntry]) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+
+        let transaction = connection.transaction().map_err(|e| e.to_string())?;
+
+        transaction
+            .execute("DELETE FROM playlists WHERE name = ?1", params![name])
+            .map_err(|e| e.to_string())?;
+
+        for (position, entry) in entries.iter().enumerate() {
+            transaction
+                .execute(
+                    "INSERT INTO playlists (name, position, map, mode) VALUES (?1, ?2, ?3, ?4)",
+                    params![name, position as i64, entry.map, entry.mode],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+
+        transaction.commit().map_err(|e| e.to_string())
+    }
+
+    pub fn add_rule(&self, action: &str, pattern: &str, added_by: &str) -> Result<i64, String> {
+        let connection = self.connection.lock().unwrap();
+
+        connection
+            .execute(
+                "INSERT INTO rules (action, pattern, added_by, added_at) VALUES (?1, ?2, ?3, ?4)",
+                params![action, pattern, added_by, now()],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    // In the order they were added
+    pub fn rules(&self) -> Result<Vec<StoredRule>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT id, action, pattern FROM rules ORDER BY id")
+            .map_err(|e| e.to_string())?;
+
+        let rules = statement
+            .query_map([], |row| {
+                Ok(StoredRule {
+                    id: row.get(0)?,
+                    action: row.get(1)?,
+                    pattern: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(rules)
+    }
+
+    // Returns whether the rule existed
+    pub fn remove_rule(&self, id: i64) -> Result<bool, String> {
+        let removed = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM rules WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn add_trigger(&self, pattern: &str, command: &str, added_by: &str) -> Result<i64, String> {
+        let connection = self.connection.lock().unwrap();
+
+        connection
+            .execute(
+                "INSERT INTO triggers (pattern, command, added_by, added_at)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![pattern, command, added_by, now()],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    // In the order they were added
+    pub fn triggers(&self) -> Result<Vec<StoredTrigger>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT id, pattern, command FROM triggers ORDER BY id")
+            .map_err(|e| e.to_string())?;
+
+        let triggers = statement
+            .query_map([], |row| {
+                Ok(StoredTrigger {
+                    id: row.get(0)?,
+                    pattern: row.get(1)?,
+                    command: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(triggers)
+    }
+
+    // Returns whether the trigger existed
+    pub fn remove_trigger(&self, id: i64) -> Result<bool, String> {
+        let removed = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM triggers WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn audit(&self, actor: &str, action: &str) -> Result<(), String> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO audit_log (time, actor, action) VALUES (?1, ?2, ?3)",
+                params![now(), actor, action],
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // The most recent entries first
+    pub fn audit_log(&self, limit: usize) -> Result<Vec<AuditEntry>, String> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection
+            .prepare("SELECT time, actor, action FROM audit_log ORDER BY id DESC LIMIT ?1")
+            .map_err(|e| e.to_string())?;
+
+        let entries = statement
+            .query_map(params![limit as i64], |row| {
+                Ok(AuditEntry {
+                    time: row.get(0)?,
+                    actor: row.get(1)?,
+                    action: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+
+        Ok(entries)
+    }
+}
+
+fn player_stats_from_row(row: &Row) -> rusqlite::Result<PlayerStats> {
+    Ok(PlayerStats {
+        uuid: row.get(0)?,
+        name: row.get(1)?,
+        first_seen: row.get(2)?,
+        last_seen: row.get(3)?,
+        sessions: row.get(4)?,
+        playtime: row.get(5)?,
+    })
+}
+
+// Unix timestamp in seconds
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_settings() {
+        let store = Store::open(":memory:").unwrap();
+        assert_eq!(store.setting("admin_chat").unwrap(), None);
+        store.set_setting("admin_chat", "1").unwrap();
+        store.set_setting("admin_chat", "2").unwrap();
+        assert_eq!(store.setting("admin_chat").unwrap().as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn decides_join_requests_once() {
+        let store = Store::open(":memory:").unwrap();
+        let request = JoinRequest {
+            id: 0,
+            uuid: String::from("6PBz8jUBWqgAAAAAjkP5Ug=="),
+            name: String::from("Anuke"),
+            user_id: 1,
+            username: String::from("@anuke"),
+            chat_id: 1,
+        };
+
+        assert_eq!(store.last_join_request(1).unwrap(), None);
+        let id = store.add_join_request(&request).unwrap();
+        assert!(store.last_join_request(1).unwrap().is_some());
+        assert!(store.has_pending_join_request(&request.uuid).unwrap());
+        assert!(store
+            .has_pending_join_request(&request.name.to_uppercase())
+            .unwrap());
+
+        let decided = store.decide_join_request(id, true, "admin").unwrap();
+        assert_eq!(
+            decided.map(|request| request.name).as_deref(),
+            Some("Anuke")
+        );
+        assert!(store
+            .decide_join_request(id, false, "admin")
+            .unwrap()
+            .is_none());
+        assert!(!store.has_pending_join_request(&request.uuid).unwrap());
+    }
+
+    #[test]
+    fn replaces_links() {
+        let store = Store::open(":memory:").unwrap();
+        let link = |user_id, uuid: &str| Link {
+            user_id,
+            uuid: String::from(uuid),
+            name: String::from("Anuke"),
+        };
+
+        store.add_link(&link(1, "a")).unwrap();
+        store.add_link(&link(2, "a")).unwrap();
+        assert!(store.link(1).unwrap().is_none());
+        assert_eq!(
+            store.link(2).unwrap().map(|link| link.uuid).as_deref(),
+            Some("a")
+        );
+
+        assert!(store.remove_link(2).unwrap());
+        assert!(store.links().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tracks_sessions() {
+        let store = Store::open(":memory:").unwrap();
+
+        store.start_session("a", "Anuke").unwrap();
+        store.end_session(None, "Anuke").unwrap();
+        store.start_session("a", "Anuke").unwrap();
+        store.start_session("b", "Other").unwrap();
+        store.end_session(Some("a"), "Anuke").unwrap();
+
+        let stats = store.player_stats("anuke").unwrap().unwrap();
+        assert_eq!(stats.uuid, "a");
+        assert_eq!(stats.sessions, 2);
+        assert!(store.player_stats("b").unwrap().is_some());
+        assert!(store.player_stats("nobody").unwrap().is_none());
+        assert_eq!(store.top_players(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn records_matches() {
+        let store = Store::open(":memory:").unwrap();
+
+        let id = store.start_match(None, Some("survival"), 1).unwrap();
+        store.set_match_map(id, "Frozen_Forest").unwrap();
+        store.update_match_peak(id, 3).unwrap();
+        store.update_match_peak(id, 2).unwrap();
+        store.end_match(id, Some(12), None).unwrap();
+        store.start_match(Some("Veins"), None, 0).unwrap();
+
+        let matches = store.matches(None).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].map.as_deref(), Some("Veins"));
+        assert_eq!(matches[1].map.as_deref(), Some("Frozen_Forest"));
+        assert_eq!(matches[1].peak_players, 3);
+        assert_eq!(matches[1].waves, Some(12));
+        assert_eq!(store.matches(Some(1)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn stores_playlists() {
+        let store = Store::open(":memory:").unwrap();
+        let entry = |map: &str| PlaylistEntry {
+            map: String::from(map),
+            mode: String::from("survival"),
+        };
+
+        store
+            .set_playlist("default", &[entry("a"), entry("b")])
+            .unwrap();
+        store.set_playlist("other", &[entry("c")]).unwrap();
+        store
+            .set_playlist("default", &[entry("b"), entry("a")])
+            .unwrap();
+
+        assert_eq!(
+            store.playlist("default").unwrap(),
+            vec![entry("b"), entry("a")]
+        );
+        assert_eq!(store.playlist("other").unwrap(), vec![entry("c")]);
+        assert!(store.playlist("none").unwrap().is_empty());
+    }
+
+    #[test]
+    fn stores_chat_formats() {
+        let store = Store::open(":memory:").unwrap();
+        store.set_chat_format(1, "plain").unwrap();
+        store.set_chat_format(1, "monospace").unwrap();
+        assert_eq!(
+            store.chat_formats().unwrap(),
+            vec![(1, String::from("monospace"))]
+        );
+    }
+
+    #[test]
+    fn stores_rules() {
+        let store = Store::open(":memory:").unwrap();
+        let first = store.add_rule("drop", "^Saved", "admin").unwrap();
+        let second = store.add_rule("alert", "Exception", "admin").unwrap();
+
+        assert!(store.remove_rule(first).unwrap());
+        assert!(!store.remove_rule(first).unwrap());
+
+        let rules = store.rules().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, second);
+        assert_eq!(rules[0].pattern, "Exception");
+    }
+
+    #[test]
+    fn keeps_audit_log() {
+        let store = Store::open(":memory:").unwrap();
+        let id = store.add_trigger("Game over!", "host", "admin").unwrap();
+        store
+            .audit("admin", "Added the trigger Game over! => host")
+            .unwrap();
+        store.audit("trigger", "Ran host").unwrap();
+
+        assert_eq!(store.triggers().unwrap()[0].command, "host");
+        assert!(store.remove_trigger(id).unwrap());
+        assert!(store.triggers().unwrap().is_empty());
+
+        let entries = store.audit_log(1).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, "trigger");
+    }
+
+    #[test]
+    fn lifts_tempbans() {
+        let store = Store::open(":memory:").unwrap();
+        let ban = TempBan {
+            id: 0,
+            uuid: String::from("6PBz8jUBWqgAAAAAjkP5Ug=="),
+            name: String::from("Anuke"),
+            reason: String::new(),
+            expires_at: now() + 60,
+        };
+
+        store.add_tempban(&ban, "admin").unwrap();
+        let tempbans = store.tempbans().unwrap();
+        assert_eq!(tempbans.len(), 1);
+
+        store.lift_tempban(tempbans[0].id).unwrap();
+        assert!(store.tempbans().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    bot::BotUpdateHandler,
+    duration,
+    store::{self, TempBan},
+};
+use std::{convert::TryFrom, time::Duration};
+use tokio::time;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The longest temporary ban, so the expiry time stays in range
+pub const MAX_DURATION: Duration = Duration::from_secs(52 * 7 * 24 * 60 * 60);
+
+// A temporary ban yet to be recorded, e.g. while it waits for confirmation
+pub struct Request {
+    pub name: String,
+    pub uuid: String,
+    pub reason: String,
+    pub duration: Duration,
+    pub banned_by: String,
+}
+
+// Records the ban and bans the player, returning the outcome to tell the user
+pub async fn apply(handler: &BotUpdateHandler, request: Request) -> String {
+    let expires_at = match i64::try_from(request.duration.as_secs())
+        .ok()
+        .and_then(|seconds| store::now().checked_add(seconds))
+    {
+        Some(expires_at) => expires_at,
+        None => return String::from("The ban is too long"),
+    };
+
+    let ban = TempBan {
+        id: 0,
+        name: request.name,
+        uuid: request.uuid,
+        reason: request.reason,
+        expires_at,
+    };
+
+    match handler.context.store.add_tempban(&ban, &request.banned_by) {
+        Ok(_) => {
+            handler.send_command(format!("ban id {}", ban.uuid)).await;
+            format!(
+                "Banned {} for {}",
+                ban.name,
+                duration::format(request.duration)
+            )
+        }
+        Err(e) => format!("Failed to record the ban: {}", e),
+    }
+}
+
+// Unbans the players whose temporary bans have expired
+pub async fn run(handler: BotUpdateHandler) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let tempbans = match handler.context.store.tempbans() {
+            Ok(tempbans) => tempbans,
+            Err(e) => {
+                log!("Failed to read the temporary bans: {}", e);
+                continue;
+            }
+        };
+
+        let now = store::now();
+
+        for ban in tempbans.iter().filter(|ban| ban.expires_at <= now) {
+            // The player stays banned if another of their bans has not expired yet
+            let is_banned = tempbans
+                .iter()
+                .any(|other| other.uuid == ban.uuid && other.expires_at > now);
+
+            if !is_banned {
+                handler.send_command(format!("unban {}", ban.uuid)).await;
+            }
+
+            match handler.context.store.lift_tempban(ban.id) {
+                Ok(_) => (),
+                Err(e) => log!("Failed to lift the temporary ban of {}: {}", ban.uuid, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::store::Store;
+use regex::Regex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, mem,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+const SEPARATOR: &str = " => ";
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+// Runs a console command when a line of the output matches the regex
+#[derive(Clone)]
+pub struct Trigger {
+    // The id in the store, or `None` for the triggers given on the command line
+    pub id: Option<i64>,
+    pub regex: Regex,
+    // May refer to the groups captured by the regex, e.g. `$1` or `${name}`
+    pub command: String,
+}
+
+impl Trigger {
+    pub fn new(id: Option<i64>, pattern: &str, command: &str) -> Result<Self, String> {
+        let command = command.trim();
+
+        if command.is_empty() {
+            return Err(String::from("A trigger needs a command to run"));
+        }
+
+        Ok(Self {
+            id,
+            regex: Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))?,
+            command: command.to_owned(),
+        })
+    }
+
+    // The command to run for the line, if it matches
+    pub fn command_for(&self, line: &str) -> Option<String> {
+        let captures = self.regex.captures(line.trim_end_matches('\n'))?;
+
+        let mut command = String::new();
+        captures.expand(&self.command, &mut command);
+
+        // A captured group must not be able to add another command
+        Some(command.replace(['\r', '\n'], " "))
+    }
+}
+
+// Triggers are written as `<regex> => <command>`, e.g. `^Game over! => host`
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, command) = s.split_once(SEPARATOR).ok_or_else(|| {
+            format!(
+                "A trigger is written as <regex>{}<command>: {}",
+                SEPARATOR, s
+            )
+        })?;
+
+        Self::new(None, pattern.trim(), command)
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.regex, SEPARATOR, self.command)
+    }
+}
+
+// The triggers given on the command line, followed by the ones added with `/triggers`
+pub fn load(config_triggers: &[Trigger], store: &Store) -> Result<Vec<Trigger>, String> {
+    let mut triggers = config_triggers.to_vec();
+
+    for stored in store.triggers()? {
+        match Trigger::new(Some(stored.id), &stored.pattern, &stored.command) {
+            Ok(trigger) => triggers.push(trigger),
+            Err(e) => log!("Ignoring the trigger {}: {}", stored.id, e),
+        }
+    }
+
+    Ok(triggers)
+}
+
+// Keeps a trigger from running in a loop, e.g. when its command outputs the line it matches
+pub struct RateLimit {
+    // The least time between two runs of a trigger
+    cooldown: Duration,
+    // The most runs of a trigger in an hour
+    hourly: usize,
+    runs: HashMap<String, Runs>,
+}
+
+#[derive(Default)]
+struct Runs {
+    times: VecDeque<Instant>,
+    // The matches skipped since the last run
+    skipped: usize,
+}
+
+impl RateLimit {
+    pub fn new(cooldown: Duration, hourly: usize) -> Self {
+        Self {
+            cooldown,
+            hourly,
+            runs: HashMap::new(),
+        }
+    }
+
+    // Returns the matches skipped since the last run if the trigger can run now
+    pub fn allow(&mut self, trigger: &Trigger, now: Instant) -> Option<usize> {
+        let runs = self.runs.entry(trigger.to_string()).or_default();
+
+        while let Some(time) = runs.times.front() {
+            if now.duration_since(*time) < HOUR {
+                break;
+            }
+            runs.times.pop_front();
+        }
+
+        let cooldown = self.cooldown;
+        let is_cooling_down = runs
+            .times
+            .back()
+            .map(|time| now.duration_since(*time) < cooldown)
+            .unwrap_or(false);
+
+        if is_cooling_down || runs.times.len() >= self.hourly {
+            runs.skipped += 1;
+            return None;
+        }
+
+        runs.times.push_back(now);
+        Some(mem::take(&mut runs.skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_captured_groups() {
+        let trigger = r"(?P<name>\w+) has connected. => say Welcome, ${name}!"
+            .parse::<Trigger>()
+            .unwrap();
+
+        assert_eq!(
+            trigger.command_for("Anuke has connected.\n").as_deref(),
+            Some("say Welcome, Anuke!")
+        );
+        assert_eq!(trigger.command_for("Map loaded.\n"), None);
+        assert_eq!(
+            trigger.to_string(),
+            r"(?P<name>\w+) has connected. => say Welcome, ${name}!"
+        );
+
+        assert!("Game over!".parse::<Trigger>().is_err());
+        assert!("Game over! => ".parse::<Trigger>().is_err());
+        assert!("( => host".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn limits_runs() {
+        let trigger = "Game over! => host".parse::<Trigger>().unwrap();
+        let mut limit = RateLimit::new(Duration::from_secs(10), 2);
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        assert_eq!(limit.allow(&trigger, at(0)), Some(0));
+        assert_eq!(limit.allow(&trigger, at(5)), None);
+        assert_eq!(limit.allow(&trigger, at(10)), Some(1));
+        assert_eq!(limit.allow(&trigger, at(20)), None);
+        assert_eq!(limit.allow(&trigger, at(3600)), Some(1));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{bot::BotUpdateHandler, store::PlaylistEntry};
+use rand::seq::SliceRandom;
+use std::{collections::HashMap, convert::TryFrom, time::Duration};
+use tgbot::{
+    methods::{SendMessage, StopPoll},
+    types::{Integer, PollAnswer},
+};
+use tokio::time;
+
+// Telegram polls have 2 to 10 options of at most 100 characters
+pub const MIN_OPTIONS: usize = 2;
+pub const MAX_OPTIONS: usize = 10;
+const OPTION_LIMIT: usize = 100;
+
+// How long to wait for Telegram to close the poll by itself before stopping it
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+// A `/votemap` poll that is still open
+pub struct MapVote {
+    pub poll_id: String,
+    pub chat_id: i64,
+    pub message_id: Integer,
+    pub maps: Vec<String>,
+    // The options chosen by each user who voted, from the poll answer updates
+    pub ballots: HashMap<Integer, Vec<Integer>>,
+}
+
+// Picks the maps to vote on at random, leaving out the ones whose names do not fit in an option
+pub fn sample(maps: &[String], count: usize) -> Vec<String> {
+    let maps = maps
+        .iter()
+        .filter(|map| map.chars().count() <= OPTION_LIMIT)
+        .collect::<Vec<_>>();
+
+    maps.choose_multiple(&mut rand::thread_rng(), count)
+        .map(|map| map.to_string())
+        .collect()
+}
+
+// Votes are only held in the chats the bot was set up for: the bridge chat, the chat with the admin
+// and the chats that get the output
+pub async fn is_vote_chat(handler: &BotUpdateHandler, chat_id: i64) -> bool {
+    handler.context.bridge_chat == Some(chat_id)
+        || *handler.context.admin_chat.lock().await == Some(chat_id)
+        || handler
+            .context
+            .output_targets()
+            .await
+            .iter()
+            .any(|(target, _)| *target == chat_id)
+}
+
+pub async fn record_answer(handler: &BotUpdateHandler, answer: PollAnswer) {
+    let mut map_vote = handler.context.map_vote.lock().await;
+
+    if let Some(map_vote) = map_vote
+        .as_mut()
+        .filter(|vote| vote.poll_id == answer.poll_id)
+    {
+        // The answer is empty when the user retracted their vote
+        if answer.option_ids.is_empty() {
+            map_vote.ballots.remove(&answer.user.id);
+        } else {
+            map_vote.ballots.insert(answer.user.id, answer.option_ids);
+        }
+    }
+}
+
+// Stops the poll if Telegram has not closed it once its time is up
+pub async fn stop_after(handler: BotUpdateHandler, poll_id: String, duration: Duration) {
+    time::sleep(duration + CLOSE_GRACE).await;
+
+    let message = match &*handler.context.map_vote.lock().await {
+        Some(map_vote) if map_vote.poll_id == poll_id => (map_vote.chat_id, map_vote.message_id),
+        _ => return,
+    };
+
+    if let Err(e) = handler
+        .api
+        .execute(StopPoll::new(message.0, message.1))
+        .await
+    {
+        log!("Failed to stop the map vote: {}", e);
+    }
+
+    close(&handler, &poll_id).await;
+}
+
+// Hosts the winning map if no game is in progress, or queues it to be hosted after the game over
+pub async fn close(handler: &BotUpdateHandler, poll_id: &str) {
+    let map_vote = {
+        let mut map_vote = handler.context.map_vote.lock().await;
+
+        match &*map_vote {
+            Some(vote) if vote.poll_id == poll_id => map_vote.take(),
+            _ => None,
+        }
+    };
+
+    let map_vote = match map_vote {
+        Some(map_vote) => map_vote,
+        None => return,
+    };
+
+    // The chat may have stopped getting the output while the poll was open
+    let is_vote_chat = is_vote_chat(handler, map_vote.chat_id).await;
+
+    let text = match winner(&map_vote.ballots, map_vote.maps.len()) {
+        Some(_) if !is_vote_chat => {
+            String::from("This chat does not get the output anymore, the next map stays unchanged")
+        }
+        Some((option, votes)) => {
+            let mode = handler
+                .context
+                .mode
+                .lock()
+                .await
+                .clone()
+                .unwrap_or_else(|| String::from("survival"));
+
+            let entry = PlaylistEntry {
+                map: map_vote.maps[option].clone(),
+                mode,
+            };
+
+            let text = format!(
+                "{} won the vote with {} vote{}",
+                entry.map,
+                votes,
+                if votes == 1 { "" } else { "s" }
+            );
+
+            if handler.context.current_match.lock().await.is_some() {
+                *handler.context.next_map.lock().await = Some(entry);
+                format!("{}. It will be hosted after the current game", text)
+            } else {
+                handler
+                    .send_command(format!("host {} {}", entry.map, entry.mode))
+                    .await;
+                format!("{}. Hosting it now", text)
+            }
+        }
+        None => String::from("No one voted, the next map stays unchanged"),
+    };
+
+    if let Err(e) = handler
+        .api
+        .execute(SendMessage::new(map_vote.chat_id, text))
+        .await
+    {
+        log!("Failed to send the result of the map vote: {}", e);
+    }
+}
+
+// The option with the most votes and its vote count. A tie goes to the option listed first
+fn winner(ballots: &HashMap<Integer, Vec<Integer>>, len: usize) -> Option<(usize, usize)> {
+    let mut counts = vec![0; len];
+
+    for option in ballots.values().flatten() {
+        if let Some(count) = usize::try_from(*option)
+            .ok()
+            .and_then(|option| counts.get_mut(option))
+        {
+            *count += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .enumerate()
+        .filter(|(_, count)| *count > 0)
+        .fold(None, |winner, (option, count)| match winner {
+            Some((_, most)) if most >= count => winner,
+            _ => Some((option, count)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_most_voted_map() {
+        let ballots = HashMap::from([(1, vec![2]), (2, vec![0]), (3, vec![2]), (4, vec![7])]);
+        assert_eq!(winner(&ballots, 3), Some((2, 2)));
+
+        // A tie goes to the option listed first
+        let ballots = HashMap::from([(1, vec![1]), (2, vec![0])]);
+        assert_eq!(winner(&ballots, 2), Some((0, 1)));
+
+        assert_eq!(winner(&HashMap::new(), 2), None);
+    }
+
+    #[test]
+    fn samples_distinct_maps() {
+        let maps = vec![
+            String::from("Ancient_Caldera"),
+            String::from("Fork"),
+            String::from("Veins"),
+            "x".repeat(OPTION_LIMIT + 1),
+        ];
+
+        let mut sample = sample(&maps, MAX_OPTIONS);
+        sample.sort();
+        assert_eq!(sample, &maps[..3]);
+    }
+}
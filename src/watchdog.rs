@@ -0,0 +1,127 @@
+use std::{
+    fmt,
+    process::Stdio,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{process::Command, time};
+
+const JSTACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long the JVM is given to print its threads after SIGQUIT
+const SIGQUIT_WAIT: Duration = Duration::from_secs(3);
+
+// How to capture the threads of a game server that stopped responding
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ThreadDump {
+    #[default]
+    None,
+    Jstack,
+    // The JVM prints its threads to the output when it receives SIGQUIT
+    Sigquit,
+}
+
+impl FromStr for ThreadDump {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "jstack" => Ok(Self::Jstack),
+            "sigquit" => Ok(Self::Sigquit),
+            _ => Err(format!("Unknown thread dump method: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ThreadDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Jstack => write!(f, "jstack"),
+            Self::Sigquit => write!(f, "sigquit"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Watchdog {
+    pub interval: Duration,
+    pub timeout: Duration,
+    // The probes missed in a row before the game server is considered hung
+    pub misses: u32,
+    pub thread_dump: ThreadDump,
+}
+
+impl Watchdog {
+    // `output` collects the output of the game process while it is `Some`, which is where the
+    // threads are printed after SIGQUIT
+    pub async fn dump_threads(
+        &self,
+        pid: u32,
+        output: &Arc<Mutex<Option<String>>>,
+    ) -> Result<Option<String>, String> {
+        match self.thread_dump {
+            ThreadDump::None => Ok(None),
+            ThreadDump::Jstack => {
+                let jstack = Command::new("jstack")
+                    .arg(pid.to_string())
+                    .stdin(Stdio::null())
+                    .kill_on_drop(true)
+                    .output();
+
+                let jstack = time::timeout(JSTACK_TIMEOUT, jstack)
+                    .await
+                    .map_err(|_| String::from("jstack timed out"))?
+                    .map_err(|e| format!("Failed to run jstack: {}", e))?;
+
+                if !jstack.status.success() {
+                    return Err(format!(
+                        "jstack failed: {}",
+                        String::from_utf8_lossy(&jstack.stderr).trim()
+                    ));
+                }
+
+                Ok(Some(String::from_utf8_lossy(&jstack.stdout).into_owned()))
+            }
+            ThreadDump::Sigquit => {
+                *output.lock().unwrap() = Some(String::new());
+
+                let kill = Command::new("kill")
+                    .arg("-QUIT")
+                    .arg(pid.to_string())
+                    .status()
+                    .await;
+
+                match kill {
+                    Ok(status) if status.success() => time::sleep(SIGQUIT_WAIT).await,
+                    Ok(status) => {
+                        output.lock().unwrap().take();
+                        return Err(format!("kill -QUIT exited with {}", status));
+                    }
+                    Err(e) => {
+                        output.lock().unwrap().take();
+                        return Err(format!("Failed to send SIGQUIT: {}", e));
+                    }
+                }
+
+                Ok(output.lock().unwrap().take())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_thread_dump_methods() {
+        for method in [ThreadDump::None, ThreadDump::Jstack, ThreadDump::Sigquit] {
+            assert_eq!(method.to_string().parse::<ThreadDump>(), Ok(method));
+        }
+        assert_eq!("jstack".parse::<ThreadDump>(), Ok(ThreadDump::Jstack));
+        assert!("gdb".parse::<ThreadDump>().is_err());
+    }
+}